@@ -0,0 +1,82 @@
+/// Remove ANSI escape sequences (CSI, OSC and two-byte escapes) from `bytes`.
+pub fn strip_ansi(bytes: &[u8]) -> Vec<u8> {
+    const ESC: u8 = 0x1b;
+    const BEL: u8 = 0x07;
+
+    let mut stripped = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != ESC {
+            stripped.push(bytes[i]);
+            i += 1;
+            continue;
+        }
+        i += 1;
+        match bytes.get(i) {
+            // CSI: parameters and intermediates up to a final byte in 0x40..=0x7e.
+            Some(b'[') => {
+                i += 1;
+                while i < bytes.len() && !(0x40..=0x7e).contains(&bytes[i]) {
+                    i += 1;
+                }
+                i += 1;
+            }
+            // OSC: terminated by BEL or ST (`ESC \`).
+            Some(b']') => {
+                i += 1;
+                while i < bytes.len() {
+                    if bytes[i] == BEL {
+                        i += 1;
+                        break;
+                    }
+                    if bytes[i] == ESC && bytes.get(i + 1) == Some(&b'\\') {
+                        i += 2;
+                        break;
+                    }
+                    i += 1;
+                }
+            }
+            Some(_) => i += 1,
+            None => {}
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_is_unchanged() {
+        assert_eq!(strip_ansi(b"plain\ttext\n"), b"plain\ttext\n");
+        assert_eq!(strip_ansi(b"\xff\xfe"), b"\xff\xfe");
+    }
+
+    #[test]
+    fn removes_csi_sequences() {
+        assert_eq!(strip_ansi(b"\x1b[1;31mred\x1b[0m ok"), b"red ok");
+        assert_eq!(strip_ansi(b"\x1b[2K\x1b[?25lline"), b"line");
+    }
+
+    #[test]
+    fn removes_osc_sequences_with_either_terminator() {
+        assert_eq!(strip_ansi(b"\x1b]0;title\x07text"), b"text");
+        assert_eq!(
+            strip_ansi(b"\x1b]8;;https://example.com\x1b\\link\x1b]8;;\x1b\\"),
+            b"link"
+        );
+    }
+
+    #[test]
+    fn removes_two_byte_escapes() {
+        assert_eq!(strip_ansi(b"a\x1b7b\x1b8c"), b"abc");
+    }
+
+    #[test]
+    fn unterminated_sequences_are_dropped() {
+        assert_eq!(strip_ansi(b"text\x1b[1;3"), b"text");
+        assert_eq!(strip_ansi(b"text\x1b]0;title"), b"text");
+        assert_eq!(strip_ansi(b"text\x1b"), b"text");
+    }
+}
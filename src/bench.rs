@@ -0,0 +1,91 @@
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::{
+    cli::ReportFormat,
+    make::{task_banner, BuildOptions, CodeRunner, Config, RunOptions},
+};
+
+/// The `--format json` report. Durations are in milliseconds; warmup runs are
+/// listed separately and left out of the statistics.
+#[derive(Serialize)]
+struct BenchReport {
+    warmup_ms: Vec<f64>,
+    runs_ms: Vec<f64>,
+    mean_ms: f64,
+    min_ms: f64,
+    max_ms: f64,
+    stddev_ms: f64,
+}
+
+/// Build a source once, then run it `warmup + runs` times on the same input and
+/// report statistics of the measured runs.
+pub fn bench(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    stdin: Option<&[u8]>,
+    runs: usize,
+    warmup: usize,
+    format: ReportFormat,
+) -> Result<()> {
+    if runs == 0 {
+        bail!("At least one measured run is needed");
+    }
+    let json = matches!(format, ReportFormat::Json);
+    let build_options = BuildOptions {
+        quiet: json,
+        ..BuildOptions::default()
+    };
+    config.build(source, output, &build_options)?;
+    task_banner("bench", &source.to_string_lossy());
+    let options = RunOptions {
+        quiet: true,
+        ..config.run_options(source, output)?
+    };
+    let mut times = Vec::with_capacity(warmup + runs);
+    for _ in 0..warmup + runs {
+        times.push(config.exec(source, output, stdin, &options)?.elapsed);
+    }
+
+    let millis: Vec<_> = times
+        .iter()
+        .map(|time| time.as_secs_f64() * 1000.0)
+        .collect();
+    let (warmup_ms, runs_ms) = millis.split_at(warmup);
+    let mean = runs_ms.iter().sum::<f64>() / runs as f64;
+    let variance = runs_ms.iter().map(|t| (t - mean).powi(2)).sum::<f64>() / runs as f64;
+    let min = runs_ms.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = runs_ms.iter().copied().fold(0.0, f64::max);
+    if json {
+        let report = BenchReport {
+            warmup_ms: warmup_ms.to_vec(),
+            runs_ms: runs_ms.to_vec(),
+            mean_ms: mean,
+            min_ms: min,
+            max_ms: max,
+            stddev_ms: variance.sqrt(),
+        };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+    let ms = |millis: f64| format!("{:.2} ms", millis);
+    let mut rows = Vec::new();
+    if warmup > 0 {
+        rows.push(("warmup", format!("{} runs excluded", warmup)));
+    }
+    rows.extend([
+        ("runs", runs.to_string()),
+        ("mean", ms(mean)),
+        ("min", ms(min)),
+        ("max", ms(max)),
+        ("stddev", ms(variance.sqrt())),
+    ]);
+    for (name, value) in rows {
+        eprintln!("   {} {}", format!("{:<8}", name).yellow().bold(), value);
+    }
+    Ok(())
+}
@@ -0,0 +1,320 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_TOLERANCE: f64 = 1e-6;
+
+/// How program output is compared against the expected output.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckMode {
+    /// Byte for byte.
+    Exact,
+    /// Line by line, ignoring trailing whitespace and trailing blank lines.
+    #[default]
+    Lines,
+    /// Whitespace-separated tokens.
+    Tokens,
+    /// Like `tokens`, but numbers may differ by up to `tol` (absolute or relative).
+    Float,
+}
+
+/// A `[[checker.override]]` entry applying to sources whose file name matches a glob.
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct CheckerOverride {
+    #[serde(rename = "match")]
+    pattern: String,
+    mode: Option<CheckMode>,
+    tol: Option<f64>,
+    max_output_bytes: Option<u64>,
+    expected_exit_code: Option<i32>,
+}
+
+/// The `[checker]` config section.
+#[derive(Deserialize, Serialize, JsonSchema)]
+pub struct CheckerConfig {
+    mode: Option<CheckMode>,
+    tol: Option<f64>,
+    /// Fail cases whose stdout is longer than this, stopping the program as soon as
+    /// it prints more.
+    max_output_bytes: Option<u64>,
+    /// Fail cases whose program exits with another code; without it, any code other
+    /// than 0 is a runtime error.
+    expected_exit_code: Option<i32>,
+    #[serde(default, rename = "override", skip_serializing_if = "Vec::is_empty")]
+    overrides: Vec<CheckerOverride>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Checker {
+    pub mode: CheckMode,
+    pub tol: f64,
+    pub max_output_bytes: Option<u64>,
+    pub expected_exit_code: Option<i32>,
+}
+
+impl Default for Checker {
+    fn default() -> Self {
+        Checker {
+            mode: CheckMode::default(),
+            tol: DEFAULT_TOLERANCE,
+            max_output_bytes: None,
+            expected_exit_code: None,
+        }
+    }
+}
+
+impl CheckerConfig {
+    /// The checker for a source. Of the overrides matching its file name, the most
+    /// specific one (the pattern with the most literal characters) wins; settings it
+    /// leaves out come from the global section.
+    pub fn resolve(&self, source: &Path) -> Result<Checker> {
+        let name = source
+            .file_name()
+            .context("Invalid source file name")?
+            .to_string_lossy();
+        let mut best: Option<(usize, &CheckerOverride)> = None;
+        for entry in &self.overrides {
+            let pattern = Pattern::new(&entry.pattern)
+                .with_context(|| format!("Invalid checker override pattern: {}", entry.pattern))?;
+            if !pattern.matches(&name) {
+                continue;
+            }
+            let specificity = entry
+                .pattern
+                .chars()
+                .filter(|c| !matches!(c, '*' | '?' | '[' | ']'))
+                .count();
+            if best.is_none_or(|(best_specificity, _)| specificity > best_specificity) {
+                best = Some((specificity, entry));
+            }
+        }
+        let entry = best.map(|(_, entry)| entry);
+        let default = Checker::default();
+        Ok(Checker {
+            mode: entry
+                .and_then(|entry| entry.mode)
+                .or(self.mode)
+                .unwrap_or(default.mode),
+            tol: entry
+                .and_then(|entry| entry.tol)
+                .or(self.tol)
+                .unwrap_or(default.tol),
+            max_output_bytes: entry
+                .and_then(|entry| entry.max_output_bytes)
+                .or(self.max_output_bytes),
+            expected_exit_code: entry
+                .and_then(|entry| entry.expected_exit_code)
+                .or(self.expected_exit_code),
+        })
+    }
+}
+
+/// Output lines with trailing whitespace and trailing blank lines removed.
+///
+/// This works on raw bytes so that output which is not valid UTF-8 is still
+/// compared exactly.
+fn normalized_lines(output: &[u8]) -> Vec<&[u8]> {
+    let mut lines: Vec<_> = output
+        .split(|&b| b == b'\n')
+        .map(<[u8]>::trim_ascii_end)
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    lines
+}
+
+fn tokens(output: &[u8]) -> Vec<&[u8]> {
+    output
+        .split(u8::is_ascii_whitespace)
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+fn parse_float(token: &[u8]) -> Option<f64> {
+    std::str::from_utf8(token).ok()?.parse().ok()
+}
+
+fn floats_match(output: &[u8], expected: &[u8], tol: f64) -> bool {
+    match (parse_float(output), parse_float(expected)) {
+        (Some(a), Some(b)) => (a - b).abs() <= tol * b.abs().max(1.0),
+        _ => output == expected,
+    }
+}
+
+/// Index of the first differing item, or the length of the shorter list if one is a
+/// prefix of the other.
+fn first_difference<T>(
+    output: &[T],
+    expected: &[T],
+    same: impl Fn(&T, &T) -> bool,
+) -> Option<usize> {
+    output
+        .iter()
+        .zip(expected)
+        .position(|(a, b)| !same(a, b))
+        .or_else(|| (output.len() != expected.len()).then(|| output.len().min(expected.len())))
+}
+
+impl Checker {
+    /// Compare program output against the expected output. Returns a description of
+    /// the first difference, or `None` when they match.
+    pub fn compare(&self, output: &[u8], expected: &[u8]) -> Option<String> {
+        match self.mode {
+            CheckMode::Exact => first_difference(output, expected, |a, b| a == b)
+                .map(|index| format!("first difference at byte {}", index)),
+            CheckMode::Lines => first_difference(
+                &normalized_lines(output),
+                &normalized_lines(expected),
+                |a, b| a == b,
+            )
+            .map(|index| format!("first difference on line {}", index + 1)),
+            CheckMode::Tokens | CheckMode::Float => {
+                let output = tokens(output);
+                let expected = tokens(expected);
+                let index = match self.mode {
+                    CheckMode::Float => {
+                        first_difference(&output, &expected, |a, b| floats_match(a, b, self.tol))
+                    }
+                    _ => first_difference(&output, &expected, |a, b| a == b),
+                }?;
+                let token = |tokens: &[&[u8]]| {
+                    tokens
+                        .get(index)
+                        .map_or("end of output".to_string(), |token| {
+                            format!("`{}`", String::from_utf8_lossy(token))
+                        })
+                };
+                Some(format!(
+                    "token {}: expected {}, got {}",
+                    index + 1,
+                    token(&expected),
+                    token(&output)
+                ))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(mode: CheckMode) -> Checker {
+        Checker {
+            mode,
+            ..Checker::default()
+        }
+    }
+
+    fn config(toml: &str) -> CheckerConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn exact_reports_the_first_differing_byte() {
+        let exact = checker(CheckMode::Exact);
+        assert_eq!(exact.compare(b"abc", b"abc"), None);
+        assert_eq!(
+            exact.compare(b"abd", b"abc").as_deref(),
+            Some("first difference at byte 2")
+        );
+        assert_eq!(
+            exact.compare(b"abc\n", b"abc").as_deref(),
+            Some("first difference at byte 3")
+        );
+    }
+
+    #[test]
+    fn lines_ignore_trailing_whitespace_and_blank_lines() {
+        let lines = checker(CheckMode::Lines);
+        assert_eq!(lines.compare(b"1 2  \n3\r\n\n\n", b"1 2\n3"), None);
+        assert_eq!(
+            lines.compare(b"1\n2\n", b"1\n3\n").as_deref(),
+            Some("first difference on line 2")
+        );
+        assert_eq!(
+            lines.compare(b"1\n", b"1\n2\n").as_deref(),
+            Some("first difference on line 2")
+        );
+        assert!(lines.compare(b" 1\n", b"1\n").is_some());
+    }
+
+    #[test]
+    fn tokens_ignore_all_whitespace() {
+        let tokens = checker(CheckMode::Tokens);
+        assert_eq!(tokens.compare(b"1  2\n\n3", b"1\n2 3\n"), None);
+        assert_eq!(
+            tokens.compare(b"1 2", b"1 3").as_deref(),
+            Some("token 2: expected `3`, got `2`")
+        );
+        assert_eq!(
+            tokens.compare(b"1", b"1 2").as_deref(),
+            Some("token 2: expected `2`, got end of output")
+        );
+    }
+
+    #[test]
+    fn floats_match_within_the_tolerance() {
+        let float = Checker {
+            tol: 1e-3,
+            ..checker(CheckMode::Float)
+        };
+        assert_eq!(float.compare(b"0.3333 yes", b"0.33333 yes"), None);
+        // Large numbers are compared relative to their size.
+        assert_eq!(float.compare(b"1000000.5", b"1000000"), None);
+        assert!(float.compare(b"0.34", b"0.33333").is_some());
+        assert!(float.compare(b"no", b"yes").is_some());
+    }
+
+    #[test]
+    fn resolve_without_overrides_uses_the_section_and_defaults() {
+        let checker = config("mode = \"tokens\"\nmax_output_bytes = 10")
+            .resolve(Path::new("sol.cpp"))
+            .unwrap();
+        assert!(matches!(checker.mode, CheckMode::Tokens));
+        assert_eq!(checker.tol, DEFAULT_TOLERANCE);
+        assert_eq!(checker.max_output_bytes, Some(10));
+        assert_eq!(checker.expected_exit_code, None);
+    }
+
+    #[test]
+    fn the_most_specific_matching_override_wins() {
+        let config = config(
+            r#"
+            mode = "lines"
+            tol = 0.5
+
+            [[override]]
+            match = "*.py"
+            mode = "tokens"
+
+            [[override]]
+            match = "float*.py"
+            mode = "float"
+
+            [[override]]
+            match = "*.cpp"
+            mode = "exact"
+            "#,
+        );
+        let resolve = |name| config.resolve(Path::new(name)).unwrap();
+        assert!(matches!(resolve("a.py").mode, CheckMode::Tokens));
+        assert!(matches!(resolve("float1.py").mode, CheckMode::Float));
+        assert!(matches!(resolve("a.rs").mode, CheckMode::Lines));
+        // Settings the override leaves out come from the section.
+        assert_eq!(resolve("float1.py").tol, 0.5);
+        // Only the file name is matched.
+        assert!(matches!(resolve("dir.py/a.rs").mode, CheckMode::Lines));
+    }
+
+    #[test]
+    fn invalid_override_patterns_are_errors() {
+        let config = config("[[override]]\nmatch = \"[\"\nmode = \"exact\"");
+        assert!(config.resolve(Path::new("a.py")).is_err());
+    }
+}
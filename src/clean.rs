@@ -0,0 +1,66 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::{
+    make::{sym, task_banner},
+    prompt,
+};
+
+/// Delete every entry of the artifact directory, after listing them and asking for
+/// confirmation. With `dry_run`, only list what would be deleted. A directory that
+/// resolves to the current one or above it, and so to the sources, is refused.
+pub fn clean(out_dir: &Path, dry_run: bool, assume_yes: bool) -> Result<()> {
+    task_banner("clean", &out_dir.to_string_lossy());
+    if !out_dir.exists() {
+        eprintln!("   {}", "nothing to clean".yellow().bold());
+        return Ok(());
+    }
+    // An artifact directory that resolves to the project, such as a symlink to `.`,
+    // holds the sources as well.
+    let resolved = out_dir.canonicalize()?;
+    if std::env::current_dir()?
+        .canonicalize()?
+        .starts_with(&resolved)
+    {
+        bail!(
+            "{} resolves to {}, which contains the sources",
+            out_dir.to_string_lossy(),
+            resolved.to_string_lossy()
+        );
+    }
+    let mut entries = fs::read_dir(out_dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort();
+    for entry in &entries {
+        eprintln!(
+            "{} {}",
+            sym::FILE.bright_white().bold(),
+            entry.to_string_lossy().bright_red()
+        );
+    }
+    if dry_run {
+        eprintln!("   {} would be removed", entries.len());
+        return Ok(());
+    }
+    if entries.is_empty() {
+        eprintln!("   {}", "nothing to clean".yellow().bold());
+        return Ok(());
+    }
+    if !prompt::confirm(&format!("Remove {} entries?", entries.len()), assume_yes)? {
+        eprintln!("   {}", "cancelled".yellow().bold());
+        return Ok(());
+    }
+    for entry in &entries {
+        if entry.is_dir() {
+            fs::remove_dir_all(entry)
+        } else {
+            fs::remove_file(entry)
+        }
+        .with_context(|| format!("Failed to remove {}", entry.to_string_lossy()))?;
+    }
+    eprintln!("   {} removed", entries.len());
+    Ok(())
+}
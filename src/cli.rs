@@ -1,11 +1,79 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    /// Color when writing to a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+/// Archive format for `cfb pack`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PackFormat {
+    Zip,
+    TarGz,
+}
+
+/// How a command reports its results.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    /// Human-readable lines on stderr
+    Text,
+    /// A JSON document on stdout
+    Json,
+}
+
+/// Signal sent to a program whose `--timeout` expired.
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum TimeoutSignal {
+    /// SIGTERM, escalating to SIGKILL after the grace period
+    Term,
+    #[default]
+    Kill,
+}
 
 #[derive(Parser)]
 pub struct Cli {
     #[clap(subcommand)]
     pub subcommand: Option<Command>,
+    /// When to use colored output
+    #[clap(long, value_enum, default_value = "auto", global = true)]
+    pub color: ColorChoice,
+    /// Print why each build was or wasn't skipped
+    #[clap(long, global = true)]
+    pub explain: bool,
+    /// Do not print the commands that run, nor the progress indicator of slow ones
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+    /// Keep the temporary directories of `use_tempdir` builds and print their paths
+    #[clap(long, global = true)]
+    pub keep_tempfiles: bool,
+    /// Compile with a `[compilers.<name>]` profile instead of the languages' own commands
+    #[clap(value_parser, long, global = true, value_name = "NAME")]
+    pub compiler: Option<String>,
+    /// Encoding of source files for reading shebangs and directives, over `source_encoding`
+    #[clap(value_parser, long, global = true, value_name = "LABEL")]
+    pub source_encoding: Option<String>,
+    /// Put artifacts in this subdirectory of cfb-out, keeping those of different
+    /// profiles apart
+    #[clap(value_parser, long, global = true, value_name = "DIR")]
+    pub output_prefix: Option<PathBuf>,
+    /// Run this command when the subcommand succeeds, and after each successful
+    /// `--watch` rerun. `{source}` is replaced by the source file and `{exit_code}`
+    /// by the exit code
+    #[clap(value_parser, long, global = true, value_name = "CMD")]
+    pub on_success: Option<String>,
+    /// Like `--on-success`, for when the subcommand fails, such as on a failing build
+    /// or test case
+    #[clap(value_parser, long, global = true, value_name = "CMD")]
+    pub on_failure: Option<String>,
+    /// Answer yes to confirmation prompts, such as the one of `clean`. Without a
+    /// terminal, prompts fail unless this is given
+    #[clap(short = 'y', long, global = true)]
+    pub assume_yes: bool,
 }
 
 #[derive(Subcommand)]
@@ -13,8 +81,354 @@ pub enum Command {
     Run {
         #[clap(value_parser)]
         source_file: PathBuf,
+        /// Feed this file to the program's stdin (`-` for that of cfb). When repeated,
+        /// the files are concatenated in order
+        #[clap(value_parser, long)]
+        stdin: Vec<PathBuf>,
+        /// Warn (without failing) when the run takes longer than this many milliseconds
+        #[clap(value_parser, long, value_name = "MS")]
+        time_limit: Option<u64>,
+        /// Kill the program after this many milliseconds, showing what it printed so far.
+        /// Defaults to `time_limit_ms` of the source's .limits.toml or .cfb/problem.toml
+        #[clap(value_parser, long, value_name = "MS")]
+        timeout: Option<u64>,
+        /// Signal sent when the timeout expires (Unix only) [default: kill]
+        #[clap(long, value_enum, ignore_case = true)]
+        timeout_signal: Option<TimeoutSignal>,
+        /// After sending TERM, wait this many milliseconds before sending KILL
+        #[clap(value_parser, long, value_name = "MS", default_value_t = 1000)]
+        timeout_grace: u64,
+        /// Limit the program's memory to this many megabytes (Unix only). Defaults to
+        /// `memory_mb` of the source's .limits.toml
+        #[clap(value_parser, long, value_name = "MB")]
+        memory_limit: Option<u64>,
+        /// Load environment variables from this .env file, over those of `env_file`
+        #[clap(value_parser, long, value_name = "FILE")]
+        env_file: Option<PathBuf>,
+        /// Do not inherit the environment: only the env files and a minimal PATH apply
+        #[clap(long)]
+        isolated_env: bool,
+        /// With false, only inherit the variables of `env_allowlist` (by default PATH,
+        /// HOME and LANG)
+        #[clap(
+            long,
+            action = clap::ArgAction::Set,
+            value_name = "BOOL",
+            default_value_t = true
+        )]
+        env_inherit: bool,
+        /// Connect the program's stdin to this named pipe (Unix only)
+        #[clap(value_parser, long, value_name = "FIFO", conflicts_with = "stdin")]
+        stdin_fifo: Option<PathBuf>,
+        /// Connect the program's stdout to this named pipe instead of printing it (Unix only)
+        #[clap(value_parser, long, value_name = "FIFO")]
+        stdout_fifo: Option<PathBuf>,
+        /// Show the output of a program that exits unsuccessfully instead of failing
+        #[clap(long)]
+        ignore_exit_code: bool,
+        /// Only print the first and last N lines of the output
+        #[clap(value_parser, long, value_name = "N")]
+        output_limit_lines: Option<usize>,
+        /// Also write the full program output to this file
+        #[clap(value_parser, long)]
+        out_file: Option<PathBuf>,
+        /// Remove ANSI escape sequences from the output written to --out-file
+        #[clap(long, requires = "out-file")]
+        strip_ansi: bool,
+        /// Name of the build artifact in cfb-out, instead of one derived from the source
+        #[clap(value_parser, long, value_name = "NAME")]
+        output_name: Option<PathBuf>,
+        /// Delete the existing artifact first, forcing a rebuild
+        #[clap(long)]
+        fresh: bool,
+        /// Always recompile, even if the artifact is up to date
+        #[clap(long)]
+        no_skip: bool,
+        /// Re-run the compile commands up to N more times when they fail
+        #[clap(value_parser, long, value_name = "N", default_value_t = 0)]
+        retry_compile: u32,
+        /// Run once for every file in this directory, with the file as stdin
+        #[clap(
+            value_parser,
+            long,
+            value_name = "DIR",
+            conflicts_with_all = &["stdin", "out-file", "time-limit"]
+        )]
+        input_dir: Option<PathBuf>,
+        /// Echo the program's stdin to stderr before running it
+        #[clap(long)]
+        print_input: bool,
+        /// With --print-input, only echo the first and last N lines of the input
+        #[clap(value_parser, long, value_name = "N", requires = "print-input")]
+        limit_lines_stdin: Option<usize>,
+        /// Print the SHA-256 of the built artifact, e.g. to check for reproducible builds
+        #[clap(long)]
+        print_hash: bool,
+        /// Only print the last N lines of the program's stderr (failed runs still show
+        /// all of it)
+        #[clap(value_parser, long, value_name = "N")]
+        tail_stderr: Option<usize>,
+        /// Rebuild and rerun whenever the source file changes, feeding the same stdin
+        /// each time
+        #[clap(long, conflicts_with_all = &["input-dir", "print-commands-only"])]
+        watch: bool,
+        /// With --watch, keep the earlier runs on screen, separated by a line
+        #[clap(long, requires = "watch")]
+        no_clear: bool,
+        /// Copy the program's stdout to the clipboard after printing it
+        #[clap(long)]
+        copy_output: bool,
+        /// Write a JSON record of the run (output, exit code, duration) to this file
+        #[clap(value_parser, long, value_name = "FILE")]
+        report: Option<PathBuf>,
+        /// Instead of building and running, write the expanded commands to this
+        /// executable shell script
+        #[clap(value_parser, long, value_name = "SCRIPT")]
+        print_commands_only: Option<PathBuf>,
+        /// Arguments for the program, after the `run_args` of its language
+        #[clap(value_parser, last = true)]
+        args: Vec<String>,
+    },
+    /// Run an already built artifact without rebuilding it
+    Exec {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        /// Feed this file to the program's stdin (`-` for that of cfb). When repeated,
+        /// the files are concatenated in order
+        #[clap(value_parser, long)]
+        stdin: Vec<PathBuf>,
+        /// Kill the program after this many milliseconds, showing what it printed so far.
+        /// Defaults to `time_limit_ms` of the source's .limits.toml or .cfb/problem.toml
+        #[clap(value_parser, long, value_name = "MS")]
+        timeout: Option<u64>,
+        /// Signal sent when the timeout expires (Unix only) [default: kill]
+        #[clap(long, value_enum, ignore_case = true)]
+        timeout_signal: Option<TimeoutSignal>,
+        /// After sending TERM, wait this many milliseconds before sending KILL
+        #[clap(value_parser, long, value_name = "MS", default_value_t = 1000)]
+        timeout_grace: u64,
+        /// Load environment variables from this .env file, over those of `env_file`
+        #[clap(value_parser, long, value_name = "FILE")]
+        env_file: Option<PathBuf>,
+        /// Do not inherit the environment: only the env files and a minimal PATH apply
+        #[clap(long)]
+        isolated_env: bool,
+        /// With false, only inherit the variables of `env_allowlist` (by default PATH,
+        /// HOME and LANG)
+        #[clap(
+            long,
+            action = clap::ArgAction::Set,
+            value_name = "BOOL",
+            default_value_t = true
+        )]
+        env_inherit: bool,
+        /// Connect the program's stdin to this named pipe (Unix only)
+        #[clap(value_parser, long, value_name = "FIFO", conflicts_with = "stdin")]
+        stdin_fifo: Option<PathBuf>,
+        /// Connect the program's stdout to this named pipe instead of printing it (Unix only)
+        #[clap(value_parser, long, value_name = "FIFO")]
+        stdout_fifo: Option<PathBuf>,
+        /// Show the output of a program that exits unsuccessfully instead of failing
+        #[clap(long)]
+        ignore_exit_code: bool,
+        /// Name of the build artifact in cfb-out, instead of one derived from the source
+        #[clap(value_parser, long, value_name = "NAME")]
+        output_name: Option<PathBuf>,
+        /// Arguments for the program, after the `run_args` of its language
+        #[clap(value_parser, last = true)]
+        args: Vec<String>,
+    },
+    /// Run a source against its test cases (`<stem>.in`/`<stem>.<n>.in` with `.out` files)
+    ///
+    /// Without a source file, the problem described by `.cfb/problem.toml` in the
+    /// current directory is tested.
+    Test {
+        #[clap(value_parser)]
+        source_file: Option<PathBuf>,
+        /// Re-run the tests whenever the source file changes
+        #[clap(long)]
+        watch: bool,
+        /// With --watch, keep the earlier runs on screen, separated by a line
+        #[clap(long, requires = "watch")]
+        no_clear: bool,
+        /// Number of test cases to run in parallel
+        #[clap(value_parser, short, long, default_value_t = 1)]
+        jobs: usize,
+        /// Fail cases that run longer than this many milliseconds, over the source's
+        /// .limits.toml and .cfb/problem.toml
+        #[clap(value_parser, long, value_name = "MS")]
+        time_limit: Option<u64>,
+        /// Limit the program's memory to this many megabytes (Unix only), over the
+        /// source's .limits.toml
+        #[clap(value_parser, long, value_name = "MB")]
+        memory_limit: Option<u64>,
+        /// Take the expected output from this command run on the same input (e.g. a
+        /// brute-force solution) instead of from .out files
+        #[clap(value_parser, long, value_name = "COMMAND")]
+        expected_cmd: Option<String>,
+        /// Print the expanded run command and input of each failing case, for
+        /// reproducing it outside cfb
+        #[clap(long)]
+        show_command: bool,
+        /// Run every case, even those accepted before with the same program and input
+        #[clap(long)]
+        no_cache: bool,
+        /// Stop after the first case that is not accepted
+        #[clap(long)]
+        fail_fast: bool,
+        /// Write a JSON record of every case (output, exit code, duration, verdict) to
+        /// this file
+        #[clap(value_parser, long, value_name = "FILE")]
+        report: Option<PathBuf>,
+    },
+    /// Run a trusted source on the inputs of its test cases and save its outputs as
+    /// their `.out` files, asking before overwriting existing ones
+    GenExpected {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+    },
+    /// Build and run a source once, reporting compile time, run time, peak memory and
+    /// exit status
+    Profile {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        /// Feed this file to the program's stdin (`-` for that of cfb). When repeated,
+        /// the files are concatenated in order
         #[clap(value_parser, long)]
-        stdin: Option<PathBuf>,
+        stdin: Vec<PathBuf>,
     },
-    BuildAll,
+    /// Build a source and play a dialog script against it: `> line` sends a line to
+    /// the program and `< line` expects its next line of output
+    Interact {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        #[clap(value_parser, long)]
+        script: PathBuf,
+        /// Wait at most this many milliseconds for each expected line
+        #[clap(value_parser, long, value_name = "MS", default_value_t = 5000)]
+        timeout: u64,
+    },
+    /// Submit a source as configured in the [submit] section, printing the response
+    Submit {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        /// Run the local tests first and only submit if they all pass
+        #[clap(long)]
+        test: bool,
+    },
+    /// Run a source on generated inputs until its output differs from a reference
+    /// solution's
+    Stress {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        /// Command printing a random input; `{seed}` is replaced by the iteration's seed
+        #[clap(value_parser, long, value_name = "COMMAND")]
+        generator: String,
+        /// Command printing the expected output for the input on its stdin (e.g. a
+        /// brute-force solution)
+        #[clap(value_parser, long, value_name = "COMMAND")]
+        expected_cmd: String,
+        #[clap(value_parser, short = 'n', long, default_value_t = 100)]
+        iterations: u64,
+        /// Seed of the first iteration; each following one uses the next number
+        #[clap(value_parser, long, default_value_t = 1)]
+        seed: u64,
+    },
+    /// Build a source, then time repeated runs of it on the same input
+    Bench {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        /// Feed this file to the program's stdin (`-` for that of cfb). When repeated,
+        /// the files are concatenated in order
+        #[clap(value_parser, long)]
+        stdin: Vec<PathBuf>,
+        /// Number of measured runs
+        #[clap(value_parser, short = 'n', long, default_value_t = 10)]
+        runs: usize,
+        /// Runs before the measured ones whose timings are discarded, to warm up caches
+        #[clap(value_parser, long, value_name = "N", default_value_t = 0)]
+        warmup: usize,
+        #[clap(long, value_enum, default_value = "text")]
+        format: ReportFormat,
+    },
+    /// Build every source below the current directory
+    BuildAll {
+        /// Only build sources of this language (repeatable)
+        #[clap(value_parser, long, value_name = "LANG")]
+        only: Vec<String>,
+        /// Do not build sources of this language (repeatable)
+        #[clap(value_parser, long, value_name = "LANG")]
+        skip: Vec<String>,
+        /// Delete everything in cfb-out (or in its `--output-prefix` directory) first,
+        /// forcing a rebuild
+        #[clap(long)]
+        fresh: bool,
+        /// Re-run the compile commands of a source up to N more times when they fail
+        #[clap(value_parser, long, value_name = "N", default_value_t = 0)]
+        retry_compile: u32,
+        /// Only print how many sources would be built, without building
+        #[clap(long, conflicts_with = "fresh")]
+        count: bool,
+        /// With --count, also list the sources
+        #[clap(long, requires = "count")]
+        list: bool,
+    },
+    /// Bundle a source and its test case files into an archive in cfb-out
+    Pack {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+        #[clap(long, value_enum, default_value = "tar-gz")]
+        format: PackFormat,
+        /// Build the source and include the artifact as well
+        #[clap(long)]
+        with_binary: bool,
+        /// Name of the build artifact in cfb-out, instead of one derived from the source
+        #[clap(value_parser, long, value_name = "NAME")]
+        output_name: Option<PathBuf>,
+    },
+    /// Delete all build artifacts in cfb-out
+    Clean {
+        /// Only list what would be deleted, without asking
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Print which language config a source file resolves to, and where it is defined
+    Which {
+        #[clap(value_parser)]
+        source_file: PathBuf,
+    },
+    /// Print the effective configuration after merging all config files
+    Config {
+        /// List the languages with the config file each one comes from instead
+        #[clap(long)]
+        verbose: bool,
+    },
+    /// Check that the configured compilers and interpreters are installed
+    Doctor,
+    /// Print a JSON Schema describing cfb.toml
+    Schema,
+}
+
+impl Command {
+    /// The source file the subcommand works on, if it works on a single one.
+    pub fn source_file(&self) -> Option<&Path> {
+        match self {
+            Command::Run { source_file, .. }
+            | Command::Exec { source_file, .. }
+            | Command::GenExpected { source_file }
+            | Command::Profile { source_file, .. }
+            | Command::Interact { source_file, .. }
+            | Command::Submit { source_file, .. }
+            | Command::Stress { source_file, .. }
+            | Command::Bench { source_file, .. }
+            | Command::Pack { source_file, .. }
+            | Command::Which { source_file } => Some(source_file),
+            Command::Test { source_file, .. } => source_file.as_deref(),
+            Command::BuildAll { .. }
+            | Command::Clean { .. }
+            | Command::Config { .. }
+            | Command::Doctor
+            | Command::Schema => None,
+        }
+    }
 }
@@ -0,0 +1,127 @@
+use crate::report::warn;
+
+/// Only this many lines at the top of a source are searched for directives.
+const HEAD_LINES: usize = 20;
+
+/// Language config keys that a directive may override.
+const KEYS: &[&str] = &[
+    "compile_commands",
+    "run_command",
+    "run_args",
+    "interpreted",
+    "artifact_stdin",
+    "verify_output",
+    "verify_output_newer",
+    "fail_on_stderr",
+    "shell",
+    "output_is_dir",
+    "strip",
+    "use_tempdir",
+    "exit_codes",
+];
+
+/// The text after `cfb:` in a comment line such as `// cfb: key = value`, whatever the
+/// comment syntax. The `cfb:` has to follow a comment marker.
+fn directive_text(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    let comment = line.trim_start_matches(|c| "/#-;%*!".contains(c));
+    if comment.len() == line.len() {
+        return None;
+    }
+    let text = comment.trim_start().strip_prefix("cfb:")?;
+    Some(text.trim().trim_end_matches("*/").trim_end())
+}
+
+/// Read the `cfb:` directives in the decoded start of a source into a table of
+/// language config overrides. Malformed or unknown directives are skipped with a warning.
+pub fn read_directives(head: &str) -> toml::value::Table {
+    let mut directives = toml::value::Table::new();
+    for (index, line) in head.lines().take(HEAD_LINES).enumerate() {
+        let Some(text) = directive_text(line) else {
+            continue;
+        };
+        let table = match toml::from_str::<toml::value::Table>(text) {
+            Ok(table) => table,
+            Err(e) => {
+                warn(&format!(
+                    "ignoring malformed directive on line {}: {}",
+                    index + 1,
+                    e
+                ));
+                continue;
+            }
+        };
+        for (key, value) in table {
+            if KEYS.contains(&key.as_str()) {
+                directives.insert(key, value);
+            } else {
+                warn(&format!(
+                    "ignoring unknown directive `{}` on line {}",
+                    key,
+                    index + 1
+                ));
+            }
+        }
+    }
+    directives
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_directives_in_any_comment_syntax() {
+        let directives = read_directives(
+            "// cfb: compile_commands = [\"cc {source}\"]\n\
+             # cfb: fail_on_stderr = true\n\
+             /* cfb: verify_output = false */\n\
+             -- cfb: run_command = \"./a.out\"\n\
+             int main() {}\n",
+        );
+        assert_eq!(directives.len(), 4);
+        assert_eq!(
+            directives["compile_commands"][0].as_str(),
+            Some("cc {source}")
+        );
+        assert_eq!(directives["fail_on_stderr"].as_bool(), Some(true));
+        assert_eq!(directives["verify_output"].as_bool(), Some(false));
+        assert_eq!(directives["run_command"].as_str(), Some("./a.out"));
+    }
+
+    #[test]
+    fn later_directives_override_earlier_ones() {
+        let directives =
+            read_directives("// cfb: verify_output = true\n// cfb: verify_output = false\n");
+        assert_eq!(directives["verify_output"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn skips_unknown_and_malformed_directives() {
+        let directives = read_directives(
+            "// cfb: run_command = \"./a.out\"\n\
+             // cfb: nonsense = 1\n\
+             // cfb: fail_on_stderr = \n\
+             // cfb: verify_output = false\n",
+        );
+        let keys: Vec<_> = directives.keys().map(String::as_str).collect();
+        assert_eq!(keys, ["run_command", "verify_output"]);
+    }
+
+    #[test]
+    fn only_the_head_of_the_source_is_searched() {
+        let head = format!("{}// cfb: verify_output = true\n", "\n".repeat(HEAD_LINES));
+        assert!(read_directives(&head).is_empty());
+        assert!(read_directives("int x; // cfb: verify_output = true\n").is_empty());
+    }
+
+    #[test]
+    fn directives_have_to_be_comments() {
+        assert!(read_directives("cfb: verify_output = true\n").is_empty());
+        assert!(read_directives("  cfb: verify_output = true\n").is_empty());
+        assert_eq!(
+            directive_text("  # cfb: verify_output = true"),
+            Some("verify_output = true")
+        );
+    }
+}
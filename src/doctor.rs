@@ -0,0 +1,106 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+
+use crate::make::{task_banner, Config};
+
+const SHELL: &str = "/bin/sh";
+
+/// Look a program up the same way the shell would: as a path if it contains a slash,
+/// otherwise in each `PATH` entry.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return path.is_file().then_some(path);
+    }
+    env::split_paths(&env::var_os("PATH")?)
+        .map(|dir| dir.join(program))
+        .find(|path| path.is_file())
+}
+
+/// The first non-empty line printed by `<program> --version`, if any. Its stdin is
+/// empty, so that a program reading it instead does not hang.
+fn program_version(path: &Path) -> Option<String> {
+    let output = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    let text = String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr);
+    text.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(str::to_string)
+}
+
+fn report(ok: bool, subject: &str, detail: &str) {
+    let status = if ok {
+        "ok".bright_green().bold()
+    } else {
+        "missing".bright_red().bold()
+    };
+    eprintln!("   {} {} {}", status, subject, detail.bright_black());
+}
+
+fn check_out_dir(out_dir: &Path) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let probe = out_dir.join(".cfb-doctor");
+    fs::write(&probe, b"")?;
+    fs::remove_file(&probe)?;
+    Ok(())
+}
+
+/// Check that the tools used by every configured language are installed.
+pub fn doctor(config: &Config, out_dir: &Path) -> Result<()> {
+    let mut problems = 0;
+
+    task_banner("check", "environment");
+    let shell_found = Path::new(SHELL).is_file();
+    report(shell_found, SHELL, "");
+    if !shell_found {
+        problems += 1;
+    }
+    match check_out_dir(out_dir) {
+        Ok(()) => report(true, &out_dir.to_string_lossy(), "writable"),
+        Err(e) => {
+            report(
+                false,
+                &out_dir.to_string_lossy(),
+                &format!("not writable: {}", e),
+            );
+            problems += 1;
+        }
+    }
+
+    let mut langs: Vec<_> = config.langs().iter().collect();
+    langs.sort_by_key(|(lang, _)| *lang);
+    for (lang, lang_config) in langs {
+        task_banner("check", lang);
+        for program in lang_config.programs() {
+            match find_program(&program) {
+                Some(path) => {
+                    let version = program_version(&path).unwrap_or_default();
+                    report(
+                        true,
+                        &program,
+                        &format!("{} {}", path.to_string_lossy(), version),
+                    );
+                }
+                None => {
+                    report(false, &program, "not found on PATH");
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    if problems > 0 {
+        bail!("{} problem(s) found", problems);
+    }
+    Ok(())
+}
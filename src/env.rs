@@ -0,0 +1,88 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+
+/// Remove one pair of matching surrounding quotes.
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value
+            .strip_prefix(quote)
+            .and_then(|value| value.strip_suffix(quote))
+        {
+            return inner;
+        }
+    }
+    value
+}
+
+/// Read the `KEY=VALUE` lines of a `.env` file. Blank lines and `#` comments are
+/// ignored, as is an `export ` prefix.
+pub fn load_env_file(path: &Path) -> Result<Vec<(String, String)>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read env file {}", path.to_string_lossy()))?;
+    parse_env_file(&contents, path)
+}
+
+/// The variables of the contents of an env file, with `path` for error messages.
+fn parse_env_file(contents: &str, path: &Path) -> Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let Some((key, value)) = line.split_once('=') else {
+            bail!(
+                "{}:{}: expected KEY=VALUE",
+                path.to_string_lossy(),
+                index + 1
+            );
+        };
+        vars.push((key.trim().to_string(), unquote(value.trim()).to_string()));
+    }
+    Ok(vars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(contents: &str) -> Result<Vec<(String, String)>> {
+        parse_env_file(contents, Path::new(".env"))
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn reads_key_value_lines_in_order() {
+        let parsed = parse("B=2\n\n# a comment\n  A = 1  \nexport C=3\n").unwrap();
+        assert_eq!(parsed, vars(&[("B", "2"), ("A", "1"), ("C", "3")]));
+    }
+
+    #[test]
+    fn removes_one_pair_of_matching_quotes() {
+        let parsed = parse("A=\"x y\"\nB='\"z\"'\nC=\"mixed'\nD=\n").unwrap();
+        assert_eq!(
+            parsed,
+            vars(&[("A", "x y"), ("B", "\"z\""), ("C", "\"mixed'"), ("D", "")])
+        );
+    }
+
+    #[test]
+    fn only_the_first_equals_sign_separates() {
+        let parsed = parse("URL=http://host/?a=b").unwrap();
+        assert_eq!(parsed, vars(&[("URL", "http://host/?a=b")]));
+    }
+
+    #[test]
+    fn lines_without_equals_sign_are_errors_with_their_line_number() {
+        let error = parse("A=1\nnot a variable\n").unwrap_err();
+        assert_eq!(error.to_string(), ".env:2: expected KEY=VALUE");
+    }
+}
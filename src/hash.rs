@@ -0,0 +1,17 @@
+use sha2::{Digest, Sha256};
+
+/// Hex-encoded SHA-256 digest of `data`.
+pub fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Hex-encoded SHA-256 digest of several values. Each part is length-prefixed, so
+/// different splits of the same bytes hash differently.
+pub fn sha256_hex_parts(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    format!("{:x}", hasher.finalize())
+}
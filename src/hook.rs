@@ -0,0 +1,74 @@
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use anyhow::Result;
+use shell_quote::sh;
+
+use crate::{
+    make::{self, CommandFailed, RunOptions},
+    report,
+};
+
+/// The exit code of cfb for an error other than a failed command.
+const EXIT_ERROR: i32 = 1;
+
+/// Commands run after a subcommand completes, from `--on-success` and `--on-failure`.
+#[derive(Default)]
+pub struct Hooks {
+    pub on_success: Option<String>,
+    pub on_failure: Option<String>,
+    pub login_shell: bool,
+    pub quiet: bool,
+}
+
+impl Hooks {
+    /// Run the hook for the outcome of a subcommand, or of one `--watch` rerun. Its
+    /// `{exit_code}` is 0 on success, that of the compiler or program whose failure
+    /// ended it, or 1 for other errors.
+    pub fn after<T>(&self, source: Option<&Path>, result: &Result<T>) {
+        let exit_code = match result {
+            Ok(_) => 0,
+            Err(e) => e
+                .downcast_ref::<CommandFailed>()
+                .and_then(|failed| failed.exit_code)
+                .filter(|&code| code != 0)
+                .unwrap_or(EXIT_ERROR),
+        };
+        self.fire(source, exit_code);
+    }
+
+    /// Run the hook for `exit_code`, with `{source}` empty for subcommands that do not
+    /// work on a single source. Failures of the hook itself are only warned about.
+    pub fn fire(&self, source: Option<&Path>, exit_code: i32) {
+        let (name, hook) = if exit_code == 0 {
+            ("--on-success", &self.on_success)
+        } else {
+            ("--on-failure", &self.on_failure)
+        };
+        let Some(hook) = hook else {
+            return;
+        };
+        let source = source
+            .map(|source| sh::quote(source.as_os_str()).to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let command = hook
+            .replace("{source}", &source)
+            .replace("{exit_code}", &exit_code.to_string());
+        let options = RunOptions {
+            login_shell: self.login_shell,
+            quiet: self.quiet,
+            ..RunOptions::default()
+        };
+        // The output of the program keeps stdout to itself.
+        match make::run_command(&command, Option::<&[u8]>::None, &options) {
+            Ok(result) => {
+                if let Err(e) = io::stderr().write_all(&result.combined()) {
+                    report::warn(&format!("could not print the {} hook output: {}", name, e));
+                }
+            }
+            Err(e) => report::warn(&format!("the {} hook failed: {:#}", name, e)),
+        }
+    }
+}
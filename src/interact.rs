@@ -0,0 +1,140 @@
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    process::Child,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::make::{task_banner, BuildOptions, CodeRunner, Config, RunOptions};
+
+/// One line of a dialog script.
+enum Step {
+    /// `> text`: write `text` to the program.
+    Send(String),
+    /// `< text`: the program's next line of output must be `text`.
+    Expect(String),
+}
+
+/// Parse a dialog script. Blank lines are skipped; every other line starts with `>`
+/// or `<`, optionally followed by a space.
+fn parse_script(script: &str) -> Result<Vec<(usize, Step)>> {
+    let mut steps = Vec::new();
+    for (index, line) in script.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut chars = line.chars();
+        let marker = chars.next();
+        let text = chars.as_str();
+        let text = text.strip_prefix(' ').unwrap_or(text).to_string();
+        let step = match marker {
+            Some('>') => Step::Send(text),
+            Some('<') => Step::Expect(text),
+            _ => bail!(
+                "Line {} of the script starts with neither `>` nor `<`",
+                index + 1
+            ),
+        };
+        steps.push((index + 1, step));
+    }
+    Ok(steps)
+}
+
+/// Play the dialog against a running program.
+fn converse(child: &mut Child, steps: &[(usize, Step)], timeout: Duration) -> Result<()> {
+    // Stdin stays with the child, so that a failed dialog kills the program before
+    // it sees the end of its input.
+    let stdin = child
+        .stdin
+        .as_mut()
+        .context("Failed to open command stdin")?;
+    let stdout = child
+        .stdout
+        .take()
+        .context("Failed to open command stdout")?;
+    // Lines are read on their own thread, so that waiting for one can time out.
+    let (sender, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    for (line_number, step) in steps {
+        match step {
+            Step::Send(text) => {
+                eprintln!("   {} {}", ">".bright_white().bold(), text.bright_black());
+                writeln!(stdin, "{}", text)
+                    .and_then(|()| stdin.flush())
+                    .with_context(|| {
+                        format!("Script line {}: the program stopped reading", line_number)
+                    })?;
+            }
+            Step::Expect(expected) => {
+                let line = match lines.recv_timeout(timeout) {
+                    Ok(line) => line?,
+                    Err(RecvTimeoutError::Timeout) => bail!(
+                        "Script line {}: no output within {} ms, expected `{}`",
+                        line_number,
+                        timeout.as_millis(),
+                        expected
+                    ),
+                    Err(RecvTimeoutError::Disconnected) => bail!(
+                        "Script line {}: the program closed its output, expected `{}`",
+                        line_number,
+                        expected
+                    ),
+                };
+                if line.trim_end() != expected.trim_end() {
+                    bail!(
+                        "Script line {}: expected `{}`, got `{}`",
+                        line_number,
+                        expected,
+                        line
+                    );
+                }
+                eprintln!("   {} {}", "<".bright_white().bold(), line.bright_black());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build a source and drive its program through a dialog script, checking each
+/// response as soon as it arrives.
+pub fn interact(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    script: &Path,
+    timeout: Duration,
+    options: &RunOptions,
+) -> Result<()> {
+    let steps = parse_script(
+        &fs::read_to_string(script)
+            .with_context(|| format!("Failed to read script {}", script.to_string_lossy()))?,
+    )?;
+    config.build(source, output, &BuildOptions::default())?;
+    task_banner("interact", &source.to_string_lossy());
+    let mut child = config.spawn_interactive(source, output, options)?;
+    let result = converse(&mut child, &steps, timeout);
+    if result.is_err() {
+        // The program may still be waiting for input that will never come.
+        let _ = child.kill();
+    }
+    drop(child.stdin.take());
+    let status = child.wait()?;
+    result?;
+    if !status.success() {
+        bail!("The program exited unsuccessfully ({})", status);
+    }
+    eprintln!("   {}", "dialog completed".green().bold());
+    Ok(())
+}
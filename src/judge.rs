@@ -0,0 +1,595 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::BufReader,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool, AtomicUsize},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use colored::{ColoredString, Colorize};
+
+use crate::{
+    checker::Checker,
+    hash,
+    make::{
+        self, sym, task_banner, BuildOptions, CodeRunner, CommandOutput, CommandOutputExceeded,
+        CommandTimedOut, Config, RunOptions,
+    },
+    problem, prompt,
+    report::{self, RunRecord},
+};
+
+/// An input file for a source, with the output it is expected to produce.
+///
+/// Cases are discovered next to the source: `sol.in` and `sol.<name>.in` are inputs
+/// for `sol.cpp`, and the matching `.out` files hold the expected outputs.
+#[derive(Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub input: PathBuf,
+    pub expected: Option<PathBuf>,
+    /// Overrides the checker's `max_output_bytes` for this case.
+    pub max_output_bytes: Option<u64>,
+    /// Overrides the checker's `expected_exit_code` for this case.
+    pub expected_exit_code: Option<i32>,
+}
+
+pub enum Verdict {
+    Accepted,
+    WrongAnswer { difference: String },
+    RuntimeError,
+    TimeLimitExceeded,
+    OutputLimitExceeded { limit: u64 },
+    NoExpected,
+}
+
+pub struct TestOptions {
+    /// Cases to run instead of the ones discovered next to the source.
+    pub cases: Option<Vec<TestCase>>,
+    pub time_limit: Option<Duration>,
+    /// Address space limit of the program in bytes.
+    pub memory_limit: Option<u64>,
+    /// Number of cases run concurrently.
+    pub jobs: usize,
+    /// Shell command whose output on the same input replaces the `.out` files.
+    pub expected_command: Option<String>,
+    /// Print the expanded run command and input of failing cases.
+    pub show_command: bool,
+    /// Skip cases that were accepted before and whose inputs have not changed.
+    pub use_cache: bool,
+    /// Stop starting new cases after the first one that is not accepted.
+    pub fail_fast: bool,
+    /// Write a JSON record of every case to this file.
+    pub report: Option<PathBuf>,
+}
+
+impl Default for TestOptions {
+    fn default() -> Self {
+        TestOptions {
+            cases: None,
+            time_limit: None,
+            memory_limit: None,
+            jobs: 1,
+            expected_command: None,
+            show_command: false,
+            use_cache: true,
+            fail_fast: false,
+            report: None,
+        }
+    }
+}
+
+impl Verdict {
+    fn code(&self) -> &'static str {
+        match self {
+            Verdict::Accepted => "AC",
+            Verdict::WrongAnswer { .. } => "WA",
+            Verdict::RuntimeError => "RE",
+            Verdict::TimeLimitExceeded => "TLE",
+            Verdict::OutputLimitExceeded { .. } => "OLE",
+            Verdict::NoExpected => "??",
+        }
+    }
+
+    fn label(&self) -> ColoredString {
+        match self {
+            Verdict::Accepted => self.code().bright_green().bold(),
+            Verdict::NoExpected => self.code().yellow().bold(),
+            _ => self.code().bright_red().bold(),
+        }
+    }
+}
+
+/// Order cases as `sol`, then numbered cases in numeric order, then named ones.
+fn case_order(a: &TestCase, b: &TestCase) -> Ordering {
+    let key = |case: &TestCase| {
+        let number = case
+            .name
+            .rsplit('.')
+            .next()
+            .and_then(|n| n.parse::<u64>().ok());
+        (
+            case.name.contains('.'),
+            number.unwrap_or(u64::MAX),
+            case.name.clone(),
+        )
+    };
+    key(a).cmp(&key(b))
+}
+
+pub fn discover_cases(source: &Path) -> Result<Vec<TestCase>> {
+    let dir = source
+        .parent()
+        .context("Source file has no parent directory")?;
+    let stem = source
+        .file_stem()
+        .context("Invalid source file name")?
+        .to_string_lossy();
+    let mut cases = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let input = entry?.path();
+        if input.extension().is_none_or(|ext| ext != "in") {
+            continue;
+        }
+        let name = input
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        if name != stem && !name.starts_with(&format!("{}.", stem)) {
+            continue;
+        }
+        let expected = input.with_extension("out");
+        cases.push(TestCase {
+            name,
+            expected: expected.exists().then_some(expected),
+            input,
+            max_output_bytes: None,
+            expected_exit_code: None,
+        });
+    }
+    cases.sort_by(case_order);
+    Ok(cases)
+}
+
+/// The outcome of running one test case.
+struct CaseResult {
+    verdict: Verdict,
+    elapsed: Option<Duration>,
+    error: Option<String>,
+    /// Accepted in an earlier run, see [`ResultsCache`].
+    cached: bool,
+    /// What the program printed, for `TestOptions::report`.
+    output: Option<CommandOutput>,
+}
+
+/// Report a case's result. `repro` is a shell command reproducing a failed run by
+/// hand, see [`Config::format_repro_command`].
+fn report(case: &TestCase, result: &CaseResult, repro: Option<&str>) {
+    let detail = match (&result.verdict, result.elapsed) {
+        _ if result.cached => "(cached)".to_string(),
+        (Verdict::WrongAnswer { difference }, _) => difference.clone(),
+        (Verdict::OutputLimitExceeded { limit }, _) => {
+            format!("more than {} bytes of output", limit)
+        }
+        (Verdict::NoExpected, _) => "no expected output".to_string(),
+        (_, Some(elapsed)) => format!("{} ms", elapsed.as_millis()),
+        (_, None) => String::new(),
+    };
+    eprintln!(
+        "   {} {} {}",
+        result.verdict.label(),
+        case.name.bright_blue(),
+        detail.bright_black()
+    );
+    if let Some(error) = &result.error {
+        eprintln!("      {}", error.bright_black());
+    }
+    if let Some(repro) = repro {
+        eprintln!(
+            "     {} {}",
+            sym::COMMAND.bright_white().bold(),
+            repro.bright_black()
+        );
+    }
+}
+
+fn run_case(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    case: &TestCase,
+    checker: &Checker,
+    options: &TestOptions,
+) -> Result<CaseResult> {
+    let stdin = BufReader::new(File::open(&case.input)?);
+    let expected_exit_code = case.expected_exit_code.or(checker.expected_exit_code);
+    let run_options = RunOptions {
+        timeout: options.time_limit,
+        memory_limit: options.memory_limit,
+        output_limit: case.max_output_bytes.or(checker.max_output_bytes),
+        // The exit code is part of the verdict then, rather than a runtime error.
+        allow_failure: expected_exit_code.is_some(),
+        // Cases run in parallel; their `$` lines would interleave with the report.
+        quiet: true,
+        ..config.run_options(source, output)?
+    };
+    let result = match config.exec(source, output, Some(stdin), &run_options) {
+        Ok(result) => result,
+        Err(e) => {
+            let verdict = if e.downcast_ref::<CommandTimedOut>().is_some() {
+                Verdict::TimeLimitExceeded
+            } else if let Some(exceeded) = e.downcast_ref::<CommandOutputExceeded>() {
+                Verdict::OutputLimitExceeded {
+                    limit: exceeded.limit,
+                }
+            } else {
+                Verdict::RuntimeError
+            };
+            return Ok(CaseResult {
+                verdict,
+                elapsed: None,
+                error: Some(e.to_string()),
+                cached: false,
+                output: None,
+            });
+        }
+    };
+    let too_slow = options
+        .time_limit
+        .is_some_and(|limit| result.elapsed > limit);
+    let expected = match (&options.expected_command, &case.expected) {
+        (Some(command), _) => {
+            let stdin = BufReader::new(File::open(&case.input)?);
+            let options = RunOptions {
+                login_shell: config.login_shell(),
+                quiet: true,
+                ..RunOptions::default()
+            };
+            let expected = make::run_command(command, Some(stdin), &options)
+                .context("Reference command failed")?;
+            Some(expected.stdout)
+        }
+        (None, Some(expected)) => Some(fs::read(expected)?),
+        (None, None) => None,
+    };
+    let exit_code = result.output.exit_code;
+    let verdict = match expected {
+        _ if too_slow => Verdict::TimeLimitExceeded,
+        _ if expected_exit_code.is_some() && exit_code.is_none() => Verdict::RuntimeError,
+        _ if expected_exit_code.is_some_and(|code| exit_code != Some(code)) => {
+            Verdict::WrongAnswer {
+                difference: format!(
+                    "exit code {}, expected {}",
+                    exit_code.unwrap_or_default(),
+                    expected_exit_code.unwrap_or_default()
+                ),
+            }
+        }
+        None if expected_exit_code.is_some() => Verdict::Accepted,
+        Some(expected) => match checker.compare(&result.output.stdout, &expected) {
+            Some(difference) => Verdict::WrongAnswer { difference },
+            None => Verdict::Accepted,
+        },
+        None => Verdict::NoExpected,
+    };
+    Ok(CaseResult {
+        verdict,
+        elapsed: Some(result.elapsed),
+        error: None,
+        cached: false,
+        output: Some(result.output),
+    })
+}
+
+/// The cases accepted in earlier runs, stored next to the artifact. A case's key
+/// covers everything its verdict depends on: the program (artifact or source, and
+/// run command), the checker, limits and environment, and the contents of its
+/// input and expected output. Only accepted cases are remembered, so failing ones
+/// always run again.
+struct ResultsCache {
+    path: PathBuf,
+    program: String,
+    accepted: HashSet<String>,
+}
+
+impl ResultsCache {
+    fn load(
+        config: &Config,
+        source: &Path,
+        output: &Path,
+        checker: &Checker,
+        options: &TestOptions,
+    ) -> Result<Self> {
+        let program = match make::read_artifact(output) {
+            Ok(artifact) => artifact,
+            Err(_) => fs::read(source)?,
+        };
+        let settings = format!(
+            "{:?} {:?} {:?} {:?} {:?} {:?}",
+            checker,
+            options.time_limit,
+            options.memory_limit,
+            options.expected_command,
+            config.env(source, output)?,
+            config.isolated_env()
+        );
+        let run_command = config.format_run_command(source, output, &[])?;
+        let path = make::results_cache_path(output);
+        Ok(ResultsCache {
+            program: hash::sha256_hex_parts(&[
+                &program,
+                run_command.as_bytes(),
+                settings.as_bytes(),
+            ]),
+            accepted: fs::read_to_string(&path)
+                .map(|stored| stored.lines().map(str::to_string).collect())
+                .unwrap_or_default(),
+            path,
+        })
+    }
+
+    fn key(&self, case: &TestCase) -> Result<String> {
+        let input = fs::read(&case.input)?;
+        let expected = match &case.expected {
+            Some(expected) => fs::read(expected)?,
+            None => Vec::new(),
+        };
+        let limit = format!("{:?} {:?}", case.max_output_bytes, case.expected_exit_code);
+        Ok(hash::sha256_hex_parts(&[
+            self.program.as_bytes(),
+            &input,
+            &expected,
+            limit.as_bytes(),
+        ]))
+    }
+
+    /// Replace the stored keys with those accepted in this run.
+    fn save(&self, accepted: &[String]) -> Result<()> {
+        fs::write(&self.path, accepted.join("\n"))
+            .with_context(|| format!("Failed to write {}", self.path.to_string_lossy()))
+    }
+}
+
+/// Run the cases on `options.jobs` threads, reporting the results in case order.
+/// Returns the number of accepted cases and the number of cases run, which is less
+/// than all of them if `fail_fast` stopped early, and with `options.report`, the
+/// records of the cases run.
+fn run_cases(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    cases: &[TestCase],
+    checker: &Checker,
+    options: &TestOptions,
+    cache: Option<&ResultsCache>,
+) -> Result<(usize, usize, Vec<RunRecord>)> {
+    let next_case = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..options.jobs.clamp(1, cases.len()) {
+            let sender = sender.clone();
+            let next_case = &next_case;
+            let stop = &stop;
+            scope.spawn(move || loop {
+                if stop.load(atomic::Ordering::Relaxed) {
+                    break;
+                }
+                let index = next_case.fetch_add(1, atomic::Ordering::Relaxed);
+                let Some(case) = cases.get(index) else {
+                    break;
+                };
+                let key = cache.map(|cache| cache.key(case)).transpose();
+                let result = match (&key, cache) {
+                    (Ok(Some(key)), Some(cache)) if cache.accepted.contains(key) => {
+                        Ok(CaseResult {
+                            verdict: Verdict::Accepted,
+                            elapsed: None,
+                            error: None,
+                            cached: true,
+                            output: None,
+                        })
+                    }
+                    _ => run_case(config, source, output, case, checker, options),
+                };
+                if sender.send((index, key, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Results arrive in completion order; hold them back until every earlier
+        // case has been reported.
+        let mut pending = BTreeMap::new();
+        let mut next_report = 0;
+        let mut passed = 0;
+        let mut accepted = Vec::new();
+        let mut records = Vec::new();
+        for (index, key, result) in receiver {
+            pending.insert(index, (key, result));
+            while let Some((key, result)) = pending.remove(&next_report) {
+                let result = result?;
+                let case = &cases[next_report];
+                let repro = if options.show_command && !matches!(result.verdict, Verdict::Accepted)
+                {
+                    config.format_repro_command(source, output, &case.input)?
+                } else {
+                    None
+                };
+                report(case, &result, repro.as_deref());
+                if options.report.is_some() {
+                    records.push(RunRecord {
+                        error: result.error.clone(),
+                        ..RunRecord::new(
+                            source,
+                            Some(&case.name),
+                            result.output.as_ref(),
+                            result.elapsed,
+                            result.verdict.code(),
+                        )
+                    });
+                }
+                if let Verdict::Accepted = result.verdict {
+                    passed += 1;
+                    accepted.extend(key?);
+                } else if options.fail_fast {
+                    stop.store(true, atomic::Ordering::Relaxed);
+                }
+                next_report += 1;
+            }
+        }
+        if let Some(cache) = cache {
+            if next_report < cases.len() {
+                // The cases that did not run keep their earlier results.
+                let new: HashSet<_> = accepted.iter().cloned().collect();
+                accepted.extend(cache.accepted.difference(&new).cloned());
+            }
+            cache.save(&accepted)?;
+        }
+        Ok((passed, next_report, records))
+    })
+}
+
+/// The discovered cases of a source, followed by those listed in its .tests.toml
+/// that were not discovered. A listed entry for a discovered input adds its
+/// settings to that case.
+fn source_cases(source: &Path) -> Result<Vec<TestCase>> {
+    let mut cases = discover_cases(source)?;
+    // Listed inputs may be spelled differently, such as `./sol.1.in`.
+    let same_file = |a: &Path, b: &Path| match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    };
+    for case in problem::load_listed_cases(source)? {
+        match cases
+            .iter_mut()
+            .find(|discovered| same_file(&discovered.input, &case.input))
+        {
+            Some(discovered) => {
+                discovered.expected = case.expected.or(discovered.expected.take());
+                discovered.max_output_bytes = case.max_output_bytes;
+                discovered.expected_exit_code = case.expected_exit_code;
+            }
+            None => cases.push(case),
+        }
+    }
+    Ok(cases)
+}
+
+/// Build the source and run it on the input of each of its test cases, writing its
+/// output as the expected output of the case. Existing expected outputs are only
+/// overwritten after confirmation; otherwise just the missing ones are written.
+pub fn gen_expected(config: &Config, source: &Path, output: &Path, assume_yes: bool) -> Result<()> {
+    let cases = source_cases(source)?;
+    if cases.is_empty() {
+        bail!(
+            "No test case inputs (.in files) found for {}",
+            source.to_string_lossy()
+        );
+    }
+    config.build(source, output, &BuildOptions::default())?;
+    task_banner("gen-expected", &source.to_string_lossy());
+    let existing: Vec<_> = cases
+        .iter()
+        .filter_map(|case| case.expected.as_ref())
+        .collect();
+    for expected in &existing {
+        eprintln!(
+            "{} {}",
+            sym::FILE.bright_white().bold(),
+            expected.to_string_lossy().bright_red()
+        );
+    }
+    let overwrite = existing.is_empty()
+        || prompt::confirm(
+            &format!("Overwrite {} expected output files?", existing.len()),
+            assume_yes,
+        )?;
+    let run_options = RunOptions {
+        quiet: true,
+        ..config.run_options(source, output)?
+    };
+    let mut written = 0;
+    for case in &cases {
+        if case.expected.is_some() && !overwrite {
+            continue;
+        }
+        let stdin = BufReader::new(File::open(&case.input)?);
+        let result = config
+            .exec(source, output, Some(stdin), &run_options)
+            .with_context(|| format!("Case {} failed", case.name))?;
+        let expected = case
+            .expected
+            .clone()
+            .unwrap_or_else(|| case.input.with_extension("out"));
+        fs::write(&expected, &result.output.stdout)
+            .with_context(|| format!("Failed to write {}", expected.to_string_lossy()))?;
+        eprintln!(
+            "   {} {}",
+            "wrote".green().bold(),
+            expected.to_string_lossy().bright_black()
+        );
+        written += 1;
+    }
+    eprintln!("   {}/{} expected outputs written", written, cases.len());
+    Ok(())
+}
+
+/// Build the source and run it against each of its test cases.
+pub fn test(config: &Config, source: &Path, output: &Path, options: &TestOptions) -> Result<()> {
+    let cases = match &options.cases {
+        Some(cases) => cases.clone(),
+        None => source_cases(source)?,
+    };
+    if cases.is_empty() {
+        bail!(
+            "No test cases (.in files) found for {}",
+            source.to_string_lossy()
+        );
+    }
+    let checker = config.checker_for(source)?;
+    config.build(source, output, &BuildOptions::default())?;
+    task_banner("test", &source.to_string_lossy());
+    let cache = if options.use_cache {
+        Some(ResultsCache::load(
+            config, source, output, &checker, options,
+        )?)
+    } else {
+        None
+    };
+    let (passed, ran, records) = run_cases(
+        config,
+        source,
+        output,
+        &cases,
+        &checker,
+        options,
+        cache.as_ref(),
+    )?;
+    if let Some(path) = &options.report {
+        report::write(path, &records)?;
+    }
+    if ran < cases.len() {
+        eprintln!(
+            "   {}/{} passed, {} not run",
+            passed,
+            cases.len(),
+            cases.len() - ran
+        );
+    } else {
+        eprintln!("   {}/{} passed", passed, cases.len());
+    }
+    if passed < cases.len() {
+        bail!("{} of {} test cases failed", ran - passed, cases.len());
+    }
+    Ok(())
+}
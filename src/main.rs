@@ -1,42 +1,971 @@
 use std::{
+    borrow::Cow,
     fs::{self, File},
-    io::BufReader,
-    path::Path,
+    io::{self, BufReader, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
 };
 
-use anyhow::{Context, Result};
-use clap::Parser;
-use cli::{Cli, Command};
-use make::CodeRunner;
+use anyhow::{bail, Context, Result};
+use clap::{CommandFactory, Parser};
+use cli::{Cli, ColorChoice, Command, TimeoutSignal};
+use colored::Colorize;
+use hook::Hooks;
+use judge::TestOptions;
+use make::{
+    sym, BuildOptions, CodeRunner, CommandOutput, CommandTimedOut, Config, DefaultCommand,
+    OutputNaming, RunOptions, RunOutput,
+};
+use problem::Problem;
+use report::RunRecord;
+mod ansi;
+mod bench;
+mod checker;
+mod clean;
 mod cli;
+mod directive;
+mod doctor;
+mod env;
+mod hash;
+mod hook;
+mod interact;
+mod judge;
 mod make;
+mod pack;
+mod problem;
+mod profile;
+mod progress;
+mod prompt;
+mod report;
+mod stress;
+mod submit;
+mod walk;
+mod watch;
+mod words;
+
+/// Directory that build artifacts are written to.
+const OUT_DIR: &str = "cfb-out";
+
+/// Exit code used when a run finished but exceeded its soft `--time-limit`.
+const EXIT_SOFT_LIMIT: i32 = 3;
+
+/// Create the artifact directory. Only commands that build call this, so the others
+/// also work in read-only directories.
+fn create_out_dir() -> Result<()> {
+    fs::create_dir_all(OUT_DIR).with_context(|| format!("Failed to create {}", OUT_DIR))
+}
+
+/// The artifact path for a source, below the `--output-prefix` if one was given. An
+/// explicit `name` replaces the derived one. A path that resolves to the source
+/// itself is an error, unless the language is interpreted and never writes it.
+fn output_path(configs: &Config, source_file: &Path, name: Option<&Path>) -> Result<PathBuf> {
+    let out_dir = match configs.output_prefix() {
+        Some(prefix) => Path::new(OUT_DIR).join(prefix),
+        None => PathBuf::from(OUT_DIR),
+    };
+    let output_file = match name {
+        Some(name) => out_dir.join(name),
+        None => {
+            let mut stem = source_file
+                .file_stem()
+                .context("Invalid source file name")?
+                .to_os_string();
+            if let Some(ext) = configs.output_ext(source_file) {
+                stem.push(".");
+                stem.push(ext);
+            }
+            let relative_dir = match configs.output_naming() {
+                OutputNaming::Stem => None,
+                OutputNaming::RelativePath => source_file.parent().and_then(|dir| {
+                    dir.strip_prefix(std::env::current_dir().ok()?.canonicalize().ok()?)
+                        .ok()
+                }),
+            };
+            match relative_dir {
+                Some(dir) => out_dir.join(dir).join(stem),
+                None => out_dir.join(stem),
+            }
+        }
+    };
+    if !configs.interpreted(source_file) {
+        make::check_output_path(source_file, &output_file)?;
+    }
+    Ok(output_file)
+}
+
+/// Open the `--stdin` file, falling back to the configured `default_stdin` or
+/// `default_stdin_cmd`.
+///
+/// The path `-` stands for the standard input of `cfb` itself.
+fn open_stdin(
+    configs: &Config,
+    stdin: &[PathBuf],
+    source_file: &Path,
+    output_file: &Path,
+) -> Result<Option<Box<dyn Read + Send>>> {
+    let paths = if stdin.is_empty() {
+        match configs.default_stdin_generator(source_file, output_file)? {
+            Some(generator) => return Ok(Some(Box::new(generator))),
+            None => configs
+                .default_stdin(source_file, output_file)?
+                .into_iter()
+                .collect(),
+        }
+    } else {
+        stdin.to_vec()
+    };
+    // Several files are read one after the other, as a single input.
+    let mut input: Option<Box<dyn Read + Send>> = None;
+    for path in paths {
+        let next: Box<dyn Read + Send> = if path.as_os_str() == "-" {
+            Box::new(io::stdin())
+        } else {
+            let file = File::open(&path)
+                .with_context(|| format!("Failed to open stdin {}", path.to_string_lossy()))?;
+            Box::new(BufReader::new(file))
+        };
+        input = Some(match input {
+            Some(input) => Box::new(input.chain(next)),
+            None => next,
+        });
+    }
+    Ok(input)
+}
+
+/// Whether the program reads the terminal of `cfb` through `--stdin -`.
+fn reads_terminal(stdin: &[PathBuf]) -> bool {
+    io::stdin().is_terminal() && stdin.iter().any(|path| path.as_os_str() == "-")
+}
+
+/// Print a program's stdout, then its stderr, tinted with `tint_stderr` so that
+/// debug prints stand out from the real output. With `limit`, only the first and
+/// last lines of each stream are kept.
+fn print_output(output: &CommandOutput, limit: Option<usize>, tint_stderr: bool) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for (bytes, tint) in [(&output.stdout, false), (&output.stderr, tint_stderr)] {
+        if limit.is_none() && !tint {
+            stdout.write_all(bytes)?;
+            continue;
+        }
+        let text = String::from_utf8_lossy(bytes);
+        let text = match limit {
+            Some(limit) => limit_lines(&text, limit),
+            None => Cow::Borrowed(text.as_ref()),
+        };
+        if tint {
+            for line in text.lines() {
+                writeln!(stdout, "{}", line.red().dimmed())?;
+            }
+        } else {
+            write!(stdout, "{}", text)?;
+        }
+    }
+    Ok(())
+}
+
+/// Print what a program printed before its timeout stopped it, which only the error
+/// of the run carries.
+fn show_partial_output<T>(result: Result<T>, limit: Option<usize>, tint_stderr: bool) -> Result<T> {
+    if let Err(e) = &result {
+        if let Some(timed_out) = e.downcast_ref::<CommandTimedOut>() {
+            print_output(&timed_out.output, limit, tint_stderr)?;
+        }
+    }
+    result
+}
+
+/// The `--report` record of a run, or of a run that failed with an error.
+fn run_record(source: &Path, case: Option<&str>, result: &Result<RunOutput>) -> RunRecord {
+    match result {
+        Ok(result) => {
+            let verdict = match result.output.exit_code {
+                Some(0) => "OK",
+                _ => "RE",
+            };
+            RunRecord::new(
+                source,
+                case,
+                Some(&result.output),
+                Some(result.elapsed),
+                verdict,
+            )
+        }
+        Err(e) => RunRecord::failed(source, case, e),
+    }
+}
+
+/// Warn when a run took longer than the soft `--time-limit` of `limit` milliseconds,
+/// returning whether it did.
+fn exceeded_soft_limit(elapsed: Duration, limit: Option<u64>) -> bool {
+    let Some(limit) = limit.map(Duration::from_millis) else {
+        return false;
+    };
+    if elapsed <= limit {
+        return false;
+    }
+    eprintln!(
+        "   {} {} ms > {} ms",
+        "exceeded soft limit".yellow().bold(),
+        elapsed.as_millis(),
+        limit.as_millis()
+    );
+    true
+}
+
+/// The `--timeout-signal` of a run, which needs a timeout, from `--timeout` or the
+/// limits of the source.
+fn checked_timeout_signal(
+    signal: Option<TimeoutSignal>,
+    timeout: Option<Duration>,
+) -> Result<TimeoutSignal> {
+    if signal.is_some() && timeout.is_none() {
+        bail!("--timeout-signal needs a timeout, from --timeout or the limits of the source");
+    }
+    Ok(signal.unwrap_or_default())
+}
+
+/// Mention an unsuccessful exit, which only reaches here with `--ignore-exit-code`.
+fn report_exit_code(output: &CommandOutput) {
+    match output.exit_code {
+        Some(0) => {}
+        Some(code) => eprintln!("   {} {}", "exit code".yellow().bold(), code),
+        None => eprintln!("   {}", "killed by a signal".yellow().bold()),
+    }
+}
+
+/// The last `count` lines of `bytes`.
+fn tail_lines(bytes: &[u8], count: usize) -> &[u8] {
+    if count == 0 {
+        return &[];
+    }
+    let body = bytes.strip_suffix(b"\n").unwrap_or(bytes);
+    let start = body
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, &byte)| byte == b'\n')
+        .nth(count - 1)
+        .map_or(0, |(index, _)| index + 1);
+    &bytes[start..]
+}
+
+/// Read all of `stdin` and echo it to stderr, returning a reader over the same bytes
+/// for the program. With `limit`, only the first and last `limit` lines are echoed;
+/// the program still gets all of them.
+fn echo_input(mut stdin: impl Read, limit: Option<usize>) -> Result<io::Cursor<Vec<u8>>> {
+    let mut input = Vec::new();
+    stdin.read_to_end(&mut input)?;
+    eprintln!(
+        "{} {}",
+        sym::FILE.bright_white().bold(),
+        "stdin".bright_blue().bold()
+    );
+    let echoed = match limit {
+        Some(limit) => Cow::Owned(
+            limit_lines(&String::from_utf8_lossy(&input), limit)
+                .into_owned()
+                .into_bytes(),
+        ),
+        None => Cow::Borrowed(&input[..]),
+    };
+    io::stderr().write_all(&echoed)?;
+    if !echoed.is_empty() && !echoed.ends_with(b"\n") {
+        eprintln!();
+    }
+    Ok(io::Cursor::new(input))
+}
+
+/// Write an executable shell script.
+fn write_script(path: &Path, contents: &str) -> Result<()> {
+    fs::write(path, contents)
+        .with_context(|| format!("Failed to write {}", path.to_string_lossy()))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+    }
+    Ok(())
+}
+
+/// The environment for a run: the configured one, then that of `--env-file`.
+fn load_env(
+    mut vars: Vec<(String, String)>,
+    env_file: Option<&Path>,
+) -> Result<Vec<(String, String)>> {
+    if let Some(env_file) = env_file {
+        vars.extend(env::load_env_file(env_file)?);
+    }
+    Ok(vars)
+}
+
+/// Put a program's stdout on the system clipboard. Without a clipboard, such as on a
+/// headless system, this only warns.
+fn copy_to_clipboard(stdout: &[u8]) {
+    let text = String::from_utf8_lossy(stdout).into_owned();
+    match arboard::Clipboard::new().and_then(|mut clipboard| clipboard.set_text(text)) {
+        Ok(()) => eprintln!("   {}", "copied stdout to the clipboard".yellow().bold()),
+        Err(e) => report::warn(&format!("could not copy the output: {}", e)),
+    }
+}
+
+/// Take the cases and time limit of a problem, then the limits of the source's
+/// `.limits.toml`, then the limits given on the command line, in increasing priority.
+fn apply_problem(
+    options: &mut TestOptions,
+    problem: Option<Problem>,
+    source_file: &Path,
+    time_limit: Option<u64>,
+    memory_limit: Option<u64>,
+) -> Result<()> {
+    if let Some(problem) = problem {
+        options.time_limit = problem.time_limit;
+        if !problem.tests.is_empty() {
+            options.cases = Some(problem.tests);
+        }
+    }
+    let limits = problem::load_limits(source_file)?;
+    options.time_limit = time_limit
+        .map(Duration::from_millis)
+        .or(limits.time_limit)
+        .or(options.time_limit);
+    options.memory_limit = memory_limit
+        .map(problem::megabytes)
+        .transpose()?
+        .or(limits.memory_limit);
+    Ok(())
+}
+
+/// Keep the first and last `limit` lines of `text`, replacing the ones in between
+/// with a marker.
+fn limit_lines(text: &str, limit: usize) -> Cow<'_, str> {
+    let lines: Vec<_> = text.lines().collect();
+    if lines.len() <= 2 * limit {
+        return Cow::Borrowed(text);
+    }
+    let mut limited = String::new();
+    for line in &lines[..limit] {
+        limited.push_str(line);
+        limited.push('\n');
+    }
+    let marker = format!("... ({} lines omitted) ...", lines.len() - 2 * limit);
+    limited.push_str(&format!("{}\n", marker.bright_black()));
+    for line in &lines[lines.len() - limit..] {
+        limited.push_str(line);
+        limited.push('\n');
+    }
+    Cow::Owned(limited)
+}
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let command = cli.subcommand.unwrap_or(Command::BuildAll);
-    let configs = make::load_config()?;
+    let use_color = |terminal: bool| match cli.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && std::env::var_os("NO_COLOR").is_none(),
+    };
+    colored::control::set_override(use_color(io::stderr().is_terminal()));
+    // Program output goes to stdout, which may be redirected on its own.
+    let tint_stderr = use_color(io::stdout().is_terminal());
+    make::set_explain(cli.explain);
+    make::set_keep_tempfiles(cli.keep_tempfiles);
+    let mut configs = make::load_config()?;
+    configs.select_compiler(cli.compiler.as_deref())?;
+    if let Some(label) = cli.source_encoding {
+        configs.set_source_encoding(label)?;
+    }
+    if let Some(prefix) = cli.output_prefix {
+        configs.set_output_prefix(prefix)?;
+    }
+    configs.set_quiet(cli.quiet);
+    let hooks = Hooks {
+        on_success: cli.on_success,
+        on_failure: cli.on_failure,
+        login_shell: configs.login_shell(),
+        quiet: cli.quiet,
+    };
+    let command = match cli.subcommand {
+        Some(command) => command,
+        None => match configs.default_command() {
+            DefaultCommand::BuildAll => Command::BuildAll {
+                only: Vec::new(),
+                skip: Vec::new(),
+                fresh: false,
+                retry_compile: 0,
+                count: false,
+                list: false,
+            },
+            DefaultCommand::Help => {
+                Cli::command().print_help()?;
+                return Ok(());
+            }
+            DefaultCommand::None => return Ok(()),
+        },
+    };
+    let source_file = command.source_file().map(Path::to_path_buf);
+    let result = execute(command, configs, &hooks, tint_stderr, cli.assume_yes);
+    hooks.after(source_file.as_deref(), &result);
+    result
+}
 
-    fs::create_dir_all(Path::new("cfb-out"))?;
+/// Run a subcommand. `--watch` reruns fire the hooks themselves, the final outcome
+/// is left to the caller.
+fn execute(
+    command: Command,
+    configs: Config,
+    hooks: &Hooks,
+    tint_stderr: bool,
+    assume_yes: bool,
+) -> Result<()> {
     match command {
-        Command::Run { source_file, stdin } => {
-            let source_file = source_file.canonicalize()?;
-            let output_file = Path::new("cfb-out").join(
-                source_file
-                    .file_stem()
-                    .context("Invalid source file name")?,
-            );
-            let stdin = if let Some(stdin) = stdin {
-                Some(BufReader::new(File::open(stdin)?))
+        Command::Run {
+            source_file,
+            stdin,
+            time_limit,
+            timeout,
+            timeout_signal,
+            timeout_grace,
+            memory_limit,
+            env_file,
+            isolated_env,
+            env_inherit,
+            stdin_fifo,
+            stdout_fifo,
+            ignore_exit_code,
+            output_limit_lines,
+            out_file,
+            strip_ansi,
+            output_name,
+            fresh,
+            no_skip,
+            retry_compile,
+            print_commands_only,
+            input_dir,
+            print_input,
+            limit_lines_stdin,
+            print_hash,
+            tail_stderr,
+            watch,
+            no_clear,
+            copy_output,
+            report,
+            args,
+        } => {
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, output_name.as_deref())?;
+            if let Some(script) = print_commands_only {
+                write_script(
+                    &script,
+                    &configs.shell_script(&source_file, &output_file, &args)?,
+                )?;
+                return Ok(());
+            }
+            create_out_dir()?;
+            if fresh {
+                make::remove_artifact(&output_file)?;
+            }
+            let base = configs.run_options(&source_file, &output_file)?;
+            let timeout = timeout.map(Duration::from_millis).or(base.timeout);
+            let options = RunOptions {
+                timeout,
+                timeout_signal: checked_timeout_signal(timeout_signal, timeout)?,
+                timeout_grace: Duration::from_millis(timeout_grace),
+                env: load_env(base.env, env_file.as_deref())?,
+                isolated_env: isolated_env || base.isolated_env,
+                env_allowlist: (!env_inherit).then(|| configs.env_allowlist()),
+                stdin_fifo,
+                stdout_fifo,
+                memory_limit: memory_limit
+                    .map(problem::megabytes)
+                    .transpose()?
+                    .or(base.memory_limit),
+                allow_failure: ignore_exit_code,
+                stdin_terminal: reads_terminal(&stdin),
+                args,
+                build: BuildOptions {
+                    no_skip,
+                    retries: retry_compile,
+                    ..BuildOptions::default()
+                },
+                ..base
+            };
+            if let Some(input_dir) = input_dir {
+                configs.build(&source_file, &output_file, &options.build)?;
+                let mut records = Vec::new();
+                for input in walk::list_inputs(&input_dir)? {
+                    eprintln!(
+                        "{} {}",
+                        sym::FILE.bright_white().bold(),
+                        input.to_string_lossy().bright_blue().bold()
+                    );
+                    let mut stdin: Box<dyn Read + Send> =
+                        Box::new(BufReader::new(File::open(&input)?));
+                    if print_input {
+                        stdin = Box::new(echo_input(stdin, limit_lines_stdin)?);
+                    }
+                    let result = show_partial_output(
+                        configs.exec(&source_file, &output_file, Some(stdin), &options),
+                        output_limit_lines,
+                        tint_stderr,
+                    );
+                    if let Some(path) = &report {
+                        let case = input.to_string_lossy();
+                        records.push(run_record(&source_file, Some(&case), &result));
+                        if result.is_err() {
+                            report::write(path, &records)?;
+                        }
+                    }
+                    let result = result?;
+                    print_output(&result.output, output_limit_lines, tint_stderr)?;
+                    report_exit_code(&result.output);
+                }
+                if let Some(path) = &report {
+                    report::write(path, &records)?;
+                }
+                return Ok(());
+            }
+            // Everything from opening stdin on is repeated for each --watch rerun, so
+            // that the same input is fed every time.
+            let run_once = || -> Result<RunOutput> {
+                let mut stdin = open_stdin(&configs, &stdin, &source_file, &output_file)?;
+                if print_input {
+                    match stdin {
+                        Some(input) => {
+                            stdin = Some(Box::new(echo_input(input, limit_lines_stdin)?))
+                        }
+                        None => eprintln!("   {}", "no stdin".yellow().bold()),
+                    }
+                }
+                let result = if print_hash {
+                    configs.build(&source_file, &output_file, &options.build)?;
+                    match make::read_artifact(&output_file) {
+                        Ok(artifact) => eprintln!(
+                            "   {} {}",
+                            "sha256".yellow().bold(),
+                            hash::sha256_hex(artifact).bright_black()
+                        ),
+                        Err(_) => eprintln!("   {}", "no artifact to hash".yellow().bold()),
+                    }
+                    configs.exec(&source_file, &output_file, stdin, &options)
+                } else {
+                    configs.run(&source_file, &output_file, stdin, &options)
+                };
+                let result = show_partial_output(result, output_limit_lines, tint_stderr)?;
+                match tail_stderr {
+                    Some(count) => {
+                        let output = CommandOutput {
+                            stdout: result.output.stdout.clone(),
+                            stderr: tail_lines(&result.output.stderr, count).to_vec(),
+                            ..result.output
+                        };
+                        print_output(&output, output_limit_lines, tint_stderr)?;
+                    }
+                    None => print_output(&result.output, output_limit_lines, tint_stderr)?,
+                }
+                report_exit_code(&result.output);
+                if copy_output {
+                    copy_to_clipboard(&result.output.stdout);
+                }
+                if let Some(out_file) = &out_file {
+                    let output = result.output.combined();
+                    let contents = if strip_ansi {
+                        ansi::strip_ansi(&output)
+                    } else {
+                        output
+                    };
+                    fs::write(out_file, contents).with_context(|| {
+                        format!("Failed to write {}", out_file.to_string_lossy())
+                    })?;
+                }
+                Ok(result)
+            };
+            // Each --watch rerun replaces the report of the previous one.
+            let run_and_report = || -> Result<RunOutput> {
+                let result = run_once();
+                if let Some(path) = &report {
+                    report::write(path, &[run_record(&source_file, None, &result)])?;
+                }
+                result
+            };
+            if watch {
+                // A slow rerun only warns; watching goes on.
+                return watch::watch(&source_file, !no_clear, || {
+                    let result = run_and_report();
+                    match &result {
+                        Ok(run) if exceeded_soft_limit(run.elapsed, time_limit) => {
+                            hooks.fire(Some(&source_file), EXIT_SOFT_LIMIT)
+                        }
+                        _ => hooks.after(Some(&source_file), &result),
+                    }
+                    result.map(drop)
+                });
+            }
+            let result = run_and_report()?;
+            if exceeded_soft_limit(result.elapsed, time_limit) {
+                hooks.fire(Some(&source_file), EXIT_SOFT_LIMIT);
+                process::exit(EXIT_SOFT_LIMIT);
+            }
+        }
+        Command::Exec {
+            source_file,
+            stdin,
+            timeout,
+            timeout_signal,
+            timeout_grace,
+            env_file,
+            isolated_env,
+            env_inherit,
+            stdin_fifo,
+            stdout_fifo,
+            ignore_exit_code,
+            output_name,
+            args,
+        } => {
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, output_name.as_deref())?;
+            let stdin_terminal = reads_terminal(&stdin);
+            let stdin = open_stdin(&configs, &stdin, &source_file, &output_file)?;
+            let base = configs.run_options(&source_file, &output_file)?;
+            let timeout = timeout.map(Duration::from_millis).or(base.timeout);
+            let options = RunOptions {
+                timeout,
+                timeout_signal: checked_timeout_signal(timeout_signal, timeout)?,
+                timeout_grace: Duration::from_millis(timeout_grace),
+                env: load_env(base.env, env_file.as_deref())?,
+                isolated_env: isolated_env || base.isolated_env,
+                env_allowlist: (!env_inherit).then(|| configs.env_allowlist()),
+                stdin_fifo,
+                stdout_fifo,
+                allow_failure: ignore_exit_code,
+                stdin_terminal,
+                args,
+                ..base
+            };
+            let result = show_partial_output(
+                configs.exec(&source_file, &output_file, stdin, &options),
+                None,
+                tint_stderr,
+            )?;
+            print_output(&result.output, None, tint_stderr)?;
+            report_exit_code(&result.output);
+        }
+        Command::Test {
+            source_file,
+            watch,
+            no_clear,
+            jobs,
+            time_limit,
+            memory_limit,
+            expected_cmd,
+            show_command,
+            no_cache,
+            fail_fast,
+            report,
+        } => {
+            create_out_dir()?;
+            let problem_dir = match &source_file {
+                Some(source_file) => source_file
+                    .canonicalize()?
+                    .parent()
+                    .context("Invalid source file name")?
+                    .to_path_buf(),
+                None => std::env::current_dir()?,
+            };
+            let problem = problem::load_problem(&problem_dir)?;
+            let source_file = source_file
+                .or_else(|| problem.as_ref()?.source.clone())
+                .context("No source file given and no source in .cfb/problem.toml")?
+                .canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            let mut options = TestOptions {
+                jobs,
+                expected_command: expected_cmd,
+                show_command,
+                use_cache: !no_cache,
+                fail_fast,
+                report,
+                ..TestOptions::default()
+            };
+            apply_problem(
+                &mut options,
+                problem,
+                &source_file,
+                time_limit,
+                memory_limit,
+            )?;
+            if watch {
+                watch::watch(&source_file, !no_clear, || {
+                    let result = judge::test(&configs, &source_file, &output_file, &options);
+                    hooks.after(Some(&source_file), &result);
+                    result
+                })?;
+            } else {
+                judge::test(&configs, &source_file, &output_file, &options)?;
+            }
+        }
+        Command::Stress {
+            source_file,
+            generator,
+            expected_cmd,
+            iterations,
+            seed,
+        } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            let options = stress::StressOptions {
+                generator,
+                expected_command: expected_cmd,
+                iterations,
+                seed,
+            };
+            stress::stress(&configs, &source_file, &output_file, &options)?;
+        }
+        Command::GenExpected { source_file } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            judge::gen_expected(&configs, &source_file, &output_file, assume_yes)?;
+        }
+        Command::Bench {
+            source_file,
+            stdin,
+            runs,
+            warmup,
+            format,
+        } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            // The input is read once, so that every run gets exactly the same bytes.
+            let input = match open_stdin(&configs, &stdin, &source_file, &output_file)? {
+                Some(mut stdin) => {
+                    let mut input = Vec::new();
+                    stdin.read_to_end(&mut input)?;
+                    Some(input)
+                }
+                None => None,
+            };
+            bench::bench(
+                &configs,
+                &source_file,
+                &output_file,
+                input.as_deref(),
+                runs,
+                warmup,
+                format,
+            )?;
+        }
+        Command::Submit { source_file, test } => {
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            let submit = configs
+                .submit()
+                .context("No [submit] section in the config")?;
+            if test || submit.test_first() {
+                create_out_dir()?;
+                let problem_dir = source_file.parent().context("Invalid source file name")?;
+                let mut options = TestOptions::default();
+                let problem = problem::load_problem(problem_dir)?;
+                apply_problem(&mut options, problem, &source_file, None, None)?;
+                judge::test(&configs, &source_file, &output_file, &options)?;
+            }
+            submit::submit(&configs, submit, &source_file, &output_file)?;
+        }
+        Command::BuildAll {
+            only,
+            skip,
+            fresh,
+            retry_compile,
+            count,
+            list,
+        } => {
+            let sources: Vec<_> =
+                walk::find_sources(&std::env::current_dir()?.canonicalize()?, &configs)?
+                    .into_iter()
+                    .filter(|source| {
+                        let language = configs.language(source).unwrap_or_default();
+                        (only.is_empty() || only.iter().any(|lang| lang == language))
+                            && !skip.iter().any(|lang| lang == language)
+                    })
+                    .collect();
+            if count {
+                if list {
+                    for source in &sources {
+                        println!("{}", source.to_string_lossy());
+                    }
+                }
+                println!("{}", sources.len());
+                return Ok(());
+            }
+            // With an output prefix, the artifacts of other prefixes are left alone.
+            let out_dir = match configs.output_prefix() {
+                Some(prefix) => Path::new(OUT_DIR).join(prefix),
+                None => PathBuf::from(OUT_DIR),
+            };
+            if fresh && out_dir.exists() {
+                fs::remove_dir_all(&out_dir)
+                    .with_context(|| format!("Failed to remove {}", out_dir.to_string_lossy()))?;
+            }
+            create_out_dir()?;
+            let build_options = BuildOptions {
+                retries: retry_compile,
+                ..BuildOptions::default()
+            };
+            let mut failed = Vec::new();
+            for source in &sources {
+                let result = output_path(&configs, source, None)
+                    .and_then(|output| configs.build(source, &output, &build_options));
+                if let Err(e) = result {
+                    eprintln!("{} {:#}", "error:".bright_red().bold(), e);
+                    failed.push(source);
+                }
+            }
+            if !failed.is_empty() {
+                for source in &failed {
+                    eprintln!(
+                        "{} {}",
+                        make::sym::FILE.bright_white().bold(),
+                        source.to_string_lossy().bright_red()
+                    );
+                }
+                bail!("{} of {} builds failed", failed.len(), sources.len());
+            }
+        }
+        Command::Profile { source_file, stdin } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            let stdin_terminal = reads_terminal(&stdin);
+            let stdin = open_stdin(&configs, &stdin, &source_file, &output_file)?;
+            profile::profile(&configs, &source_file, &output_file, stdin, stdin_terminal)?;
+        }
+        Command::Interact {
+            source_file,
+            script,
+            timeout,
+        } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let output_file = output_path(&configs, &source_file, None)?;
+            let options = configs.run_options(&source_file, &output_file)?;
+            interact::interact(
+                &configs,
+                &source_file,
+                &output_file,
+                &script,
+                Duration::from_millis(timeout),
+                &options,
+            )?;
+        }
+        Command::Pack {
+            source_file,
+            format,
+            with_binary,
+            output_name,
+        } => {
+            create_out_dir()?;
+            let source_file = source_file.canonicalize()?;
+            let artifact = if with_binary {
+                let output_file = output_path(&configs, &source_file, output_name.as_deref())?;
+                configs.build(&source_file, &output_file, &BuildOptions::default())?;
+                Some(output_file)
             } else {
                 None
             };
-            let output = configs.run(&source_file, &output_file, stdin)?;
-            print!("{}", output);
+            pack::pack(
+                &source_file,
+                artifact.as_deref(),
+                format,
+                Path::new(OUT_DIR),
+            )?;
+        }
+        Command::Clean { dry_run } => {
+            clean::clean(Path::new(OUT_DIR), dry_run, assume_yes)?;
+        }
+        Command::Which { source_file } => {
+            let Some(resolution) = configs.resolve(&source_file) else {
+                let mut extensions: Vec<_> = configs.langs().keys().collect();
+                extensions.sort();
+                let extensions: Vec<_> = extensions.iter().map(|ext| format!(".{}", ext)).collect();
+                bail!(
+                    "No match for {}; known extensions: {}",
+                    source_file.to_string_lossy(),
+                    extensions.join(", ")
+                );
+            };
+            if resolution.excluded {
+                bail!(
+                    "{} has the {} of language {}, but its match_glob excludes it",
+                    source_file.to_string_lossy(),
+                    resolution.matched_by,
+                    resolution.language
+                );
+            }
+            println!("language: {}", resolution.language);
+            println!("matched by: {}", resolution.matched_by);
+            println!("defined in: {}", resolution.config_file.to_string_lossy());
+        }
+        Command::Config { verbose: false } => {
+            print!("{}", configs.to_toml()?);
         }
-        Command::BuildAll => {
-            unimplemented!();
+        Command::Config { verbose: true } => {
+            for (language, config_file) in configs.language_origins() {
+                println!("{}: {}", language, config_file.to_string_lossy());
+            }
+        }
+        Command::Doctor => {
+            doctor::doctor(&configs, Path::new(OUT_DIR))?;
+        }
+        Command::Schema => {
+            println!("{}", Config::schema()?);
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_lines_keeps_short_text() {
+        assert!(matches!(
+            limit_lines("1\n2\n3\n4\n", 2),
+            Cow::Borrowed("1\n2\n3\n4\n")
+        ));
+        assert!(matches!(limit_lines("", 0), Cow::Borrowed("")));
+    }
+
+    #[test]
+    fn limit_lines_keeps_the_first_and_last_lines() {
+        let limited = limit_lines("1\n2\n3\n4\n5\n6\n7", 2);
+        let lines: Vec<_> = limited.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[..2], ["1", "2"]);
+        assert!(lines[2].contains("(3 lines omitted)"));
+        assert_eq!(lines[3..], ["6", "7"]);
+    }
+
+    #[test]
+    fn limit_lines_of_zero_only_leaves_the_marker() {
+        let limited = limit_lines("1\n2\n", 0);
+        assert_eq!(limited.lines().count(), 1);
+        assert!(limited.contains("(2 lines omitted)"));
+    }
+
+    #[test]
+    fn tail_lines_keeps_the_last_lines() {
+        assert_eq!(tail_lines(b"1\n2\n3\n", 2), b"2\n3\n");
+        assert_eq!(tail_lines(b"1\n2\n3", 2), b"2\n3");
+        assert_eq!(tail_lines(b"1\n2\n", 5), b"1\n2\n");
+        assert_eq!(tail_lines(b"1\n2\n", 0), b"");
+        assert_eq!(tail_lines(b"", 3), b"");
+    }
+
+    #[test]
+    fn tail_lines_counts_blank_lines() {
+        assert_eq!(tail_lines(b"1\n\n\n", 2), b"\n\n");
+    }
+}
@@ -1,48 +1,515 @@
 use std::{
     borrow::Cow,
     collections::HashMap,
-    fs::File,
-    io::{self, Write},
-    path::Path,
-    process::{Command, Stdio},
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
 };
 
 use anyhow::{bail, Context, Error, Result};
 use colored::Colorize;
+use directories::ProjectDirs;
 use dynfmt::{Format, SimpleCurlyFormat};
-use serde::Deserialize;
+use encoding_rs::{Encoding, UTF_8};
+use glob::Pattern;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use shell_quote::sh;
 
-#[derive(Deserialize)]
+use crate::{
+    checker::{Checker, CheckerConfig},
+    cli::TimeoutSignal,
+    directive, doctor, env, hash, problem, progress, report,
+    submit::SubmitConfig,
+    words,
+};
+
+/// An entry of `compile_commands`: a single command, a nested array of commands
+/// that run concurrently, or a table with settings for a command.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(untagged)]
+enum CompileStep {
+    Command(String),
+    Group(Vec<String>),
+    /// During a rebuild, the command is skipped while `output` is newer than all of
+    /// `inputs` (by default `{source}`). With `capture_to`, the command's stdout is
+    /// written to that file, for transpilers that print the program they produce.
+    /// The paths take the placeholders of `default_stdin`.
+    Declared {
+        command: String,
+        output: Option<String>,
+        #[serde(default)]
+        inputs: Vec<String>,
+        capture_to: Option<String>,
+    },
+}
+
+impl CompileStep {
+    fn commands(&self) -> &[String] {
+        match self {
+            CompileStep::Command(command) => std::slice::from_ref(command),
+            CompileStep::Group(commands) => commands,
+            CompileStep::Declared { command, .. } => std::slice::from_ref(command),
+        }
+    }
+
+    /// The file receiving the command's stdout, with its placeholders expanded.
+    fn capture_path(&self, source: &Path, output: &Path) -> Result<Option<PathBuf>> {
+        match self {
+            CompileStep::Declared {
+                capture_to: Some(capture_to),
+                ..
+            } => format_path(capture_to, source, output).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether the step's declared output is up to date with its inputs. Steps
+    /// without a declared output always run.
+    fn is_fresh(&self, source: &Path, output: &Path) -> Result<bool> {
+        let CompileStep::Declared {
+            output: Some(step_output),
+            inputs,
+            ..
+        } = self
+        else {
+            return Ok(false);
+        };
+        let Some(output_modified) = newest_modified(&format_path(step_output, source, output)?)
+        else {
+            return Ok(false);
+        };
+        let default_inputs = ["{source}".to_string()];
+        let inputs = if inputs.is_empty() {
+            &default_inputs[..]
+        } else {
+            inputs
+        };
+        for input in inputs {
+            match newest_modified(&format_path(input, source, output)?) {
+                Some(input_modified) if input_modified <= output_modified => {}
+                _ => return Ok(false),
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// The `run_command`: a single command, or a pipeline of stages in which each stage's
+/// stdout is the next one's stdin. The output of the last stage is the program output,
+/// and its exit status is the pipeline's.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+#[serde(untagged)]
+enum RunCommand {
+    Command(String),
+    Pipeline(Vec<String>),
+}
+
+impl RunCommand {
+    fn stages(&self) -> &[String] {
+        match self {
+            RunCommand::Command(command) => std::slice::from_ref(command),
+            RunCommand::Pipeline(stages) => stages,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
 pub struct LanguageConfig {
-    compile_commands: Vec<String>,
-    run_command: String,
+    /// Another language config whose keys this one inherits, such as a `_base` entry.
+    /// It is resolved while merging; the field only describes the key in the schema.
+    #[allow(dead_code)]
+    #[serde(default, skip_serializing)]
+    base: Option<String>,
+    #[serde(default)]
+    compile_commands: Vec<CompileStep>,
+    /// Required, but it can be inherited from the `base`.
+    #[schemars(with = "Option<RunCommand>")]
+    run_command: RunCommand,
+    /// The language has no build step: the compile commands and the artifact checks
+    /// are skipped, and `{output}` in the run command is the source itself.
+    interpreted: Option<bool>,
+    /// Stream the artifact into the run command's stdin.
+    artifact_stdin: Option<ArtifactStdin>,
+    /// Arguments passed to every run of the program, followed by those given after `--`
+    /// on the command line. They replace an `{args}` placeholder of the run command,
+    /// and are appended to its last stage if it has none.
+    #[serde(default)]
+    run_args: Vec<String>,
+    /// File name patterns that a source with this language's extension also has to
+    /// match, such as `sol*`. A pattern starting with `!` excludes the names it
+    /// matches instead, such as `!*.debug.*`.
+    #[serde(default)]
+    match_glob: Vec<String>,
+    /// Whether to check that compiling produced a non-empty output file (default: true).
+    verify_output: Option<bool>,
+    /// Fail the build if the output was not modified after the source, which catches
+    /// compilers that report success without writing the artifact (default: false).
+    verify_output_newer: Option<bool>,
+    /// Treat any stderr output of a compile command as a failure, e.g. for warnings.
+    fail_on_stderr: Option<bool>,
+    /// Isolate the run command (but not the compile commands); defaults to the
+    /// top-level `sandbox`.
+    sandbox: Option<Sandbox>,
+    /// Whether the run command goes through `/bin/sh -c` (default: true). Without a
+    /// shell it is split into words and run directly, or by the sandbox, with
+    /// placeholders substituted unquoted into single arguments.
+    shell: Option<bool>,
+    /// The compile commands write a directory rather than a single file: `{output}`
+    /// is created as a directory and its newest file counts for the up-to-date check.
+    output_is_dir: Option<bool>,
+    /// Run `strip` on the artifact after a successful build (Unix only), making it
+    /// smaller. Skipped with a warning when `strip` is not installed.
+    strip: Option<bool>,
+    /// Compile in a fresh temporary directory, which is the working directory of the
+    /// compile commands and holds `{output}`, and copy only the artifact to `cfb-out`.
+    /// Intermediate files are removed with the directory.
+    use_tempdir: Option<bool>,
+    /// Messages explaining what exit codes of the run command mean, such as
+    /// `124 = "timed out (external)"`, shown when it fails with one of them.
+    #[serde(default)]
+    exit_codes: HashMap<String, String>,
+    /// Extension of the build artifact, e.g. `wasm` for `cfb-out/sol.wasm`.
+    output_ext: Option<String>,
+    /// The runtime that executes the artifact, substituted for `{runtime}`, such as
+    /// `wasmtime` for WebAssembly; defaults to the top-level `runtime`.
+    runtime: Option<String>,
+    /// Directory of the config file defining this language, for `{config_dir}`.
+    #[serde(skip)]
+    config_dir: PathBuf,
+    /// Copied from the top-level `login_shell`.
+    #[serde(skip)]
+    login_shell: bool,
+    /// The compile commands of the selected compiler profile, replacing `compile_commands`.
+    #[serde(skip)]
+    compiler: Option<Vec<CompileStep>>,
+}
+
+/// A `[compilers.<name>]` block, selected with `--compiler` or `default_compiler`.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct CompilerProfile {
+    /// The languages whose compile commands this replaces; by default every language
+    /// that has compile commands.
+    langs: Option<Vec<String>>,
+    compile_commands: Vec<CompileStep>,
 }
 
+/// The `PATH` of a program run with `RunOptions::isolated_env`.
+const ISOLATED_PATH: &str = "/usr/local/bin:/usr/bin:/bin";
+
+const WAIT_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
 pub type LanguageConfigs = HashMap<String, LanguageConfig>;
 
-#[derive(Deserialize)]
+/// What a bare `cfb` invocation (without a subcommand) does.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum DefaultCommand {
+    #[default]
+    BuildAll,
+    Help,
+    None,
+}
+
+/// How the build artifact is fed to the run command's stdin, for runners that read
+/// the compiled program from there rather than from a path.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtifactStdin {
+    /// The artifact is the whole stdin; the given input is not used.
+    Replace,
+    /// The artifact comes first, followed by the given input.
+    Prepend,
+}
+
+/// A tool that isolates the run command with a read-only filesystem and no network.
+///
+/// The tool has to be installed; running fails otherwise.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Sandbox {
+    Bwrap,
+    Firejail,
+}
+
+impl Sandbox {
+    fn program(self) -> &'static str {
+        match self {
+            Sandbox::Bwrap => "bwrap",
+            Sandbox::Firejail => "firejail",
+        }
+    }
+
+    /// The sandbox program and its flags, to be followed by the command it runs.
+    fn prefix(self) -> Result<Vec<&'static str>> {
+        if doctor::find_program(self.program()).is_none() {
+            bail!("Sandbox {} is not installed", self.program());
+        }
+        let flags = match self {
+            Sandbox::Bwrap => {
+                "--ro-bind / / --dev /dev --proc /proc --tmpfs /tmp --unshare-net --die-with-parent"
+            }
+            Sandbox::Firejail => "--quiet --net=none --read-only=/ --private-tmp",
+        };
+        Ok(std::iter::once(self.program())
+            .chain(flags.split(' '))
+            .collect())
+    }
+
+    /// Wrap a shell command to run inside the sandbox.
+    fn wrap(self, command: &str, login_shell: bool) -> Result<String> {
+        Ok(format!(
+            "{} /bin/sh {} {}",
+            self.prefix()?.join(" "),
+            if login_shell { "-lc" } else { "-c" },
+            sh::quote(command).to_string_lossy()
+        ))
+    }
+}
+
+/// How the artifact name in `cfb-out` is derived from a source path.
+#[derive(Deserialize, Serialize, JsonSchema, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputNaming {
+    /// `dir/sol.cpp` builds `cfb-out/sol`.
+    #[default]
+    Stem,
+    /// `dir/sol.cpp` builds `cfb-out/dir/sol`, so equal stems in different
+    /// directories do not overwrite each other.
+    RelativePath,
+}
+
+#[derive(Deserialize, Serialize, JsonSchema)]
 pub struct Config {
+    #[serde(default)]
     langs: LanguageConfigs,
     default_stdin: Option<String>,
+    /// A generator command whose stdout is used as the default stdin instead of a file.
+    default_stdin_cmd: Option<String>,
+    default_command: Option<DefaultCommand>,
+    require_default_stdin: Option<bool>,
+    output_naming: Option<OutputNaming>,
+    case_sensitive_extensions: Option<bool>,
+    /// Maps shebang interpreters (such as `python3`) to language names, for
+    /// sources without an extension.
+    #[serde(default)]
+    shebang: HashMap<String, String>,
+    checker: Option<CheckerConfig>,
+    /// Run all commands through a login shell, for toolchains that only a login
+    /// profile puts on `PATH`.
+    login_shell: Option<bool>,
+    sandbox: Option<Sandbox>,
+    /// A `.env` file loaded into the environment of run commands. Placeholders are
+    /// expanded as in `default_stdin`; a missing file is skipped.
+    env_file: Option<String>,
+    /// Run programs with only the configured environment and a minimal `PATH`,
+    /// instead of inheriting the environment of `cfb`.
+    isolated_env: Option<bool>,
+    /// The variables a program keeps from the environment of `cfb` with
+    /// `--env-inherit=false`; by default `PATH`, `HOME` and `LANG`.
+    env_allowlist: Option<Vec<String>>,
+    #[serde(default)]
+    compilers: HashMap<String, CompilerProfile>,
+    /// The compiler profile used when `--compiler` is not given.
+    default_compiler: Option<String>,
+    /// The encoding of source files, such as `latin1`, for reading their shebang line
+    /// and `cfb:` directives. Compilers read the sources themselves.
+    source_encoding: Option<String>,
+    /// Apply the `cfb:` directives at the top of sources (default: false). They can
+    /// change the commands that run, so only enable this for sources you trust.
+    allow_source_directives: Option<bool>,
+    submit: Option<SubmitConfig>,
+    /// The default `runtime` of the languages.
+    runtime: Option<String>,
+    /// A subdirectory of `cfb-out` for the artifacts, from `--output-prefix`.
+    #[serde(skip)]
+    output_prefix: Option<PathBuf>,
+    /// From `--quiet`: no command is printed and no progress indicator drawn.
+    #[serde(skip)]
+    quiet: bool,
+}
+
+/// The language config a source resolves to, and why.
+pub struct Resolution<'a> {
+    pub language: &'a str,
+    /// The file name does not pass the language's `match_glob`, so it is not built.
+    pub excluded: bool,
+    pub matched_by: String,
+    /// The config file defining the language.
+    pub config_file: PathBuf,
 }
 
-mod sym {
+pub mod sym {
     pub const COMMAND: &str = " $";
     pub const TASK: &str = " %";
     pub const FILE: &str = "::";
 }
 
-fn format_command(command: &str, source: &Path, output: &Path) -> Result<String> {
-    let mut format_args = HashMap::new();
-    let source_quoted = sh::quote(source);
-    let output_quoted = sh::quote(output);
-    format_args.insert("source", source_quoted.to_string_lossy());
-    format_args.insert("output", output_quoted.to_string_lossy());
-    format_args.insert("source_unquoted", source.to_string_lossy());
-    format_args.insert("output_unquoted", output.to_string_lossy());
+/// Settings of a single build.
+#[derive(Default, Clone, Copy)]
+pub struct BuildOptions {
+    /// Rebuild even when the artifact is up to date.
+    pub no_skip: bool,
+    /// Do not print the compile commands, keeping stdout free for a report.
+    pub quiet: bool,
+    /// Re-run the whole compile sequence up to this many more times when it fails.
+    pub retries: u32,
+}
+
+/// Limits applied to the run phase of a program.
+#[derive(Default, Clone)]
+pub struct RunOptions {
+    pub timeout: Option<Duration>,
+    pub timeout_signal: TimeoutSignal,
+    /// How long a program may take to exit after `TimeoutSignal::Term`.
+    pub timeout_grace: Duration,
+    /// Fail when the command writes anything (other than whitespace) to stderr.
+    pub fail_on_stderr: bool,
+    /// Run the command through a login shell (`sh -lc`), which sets up `PATH` from
+    /// the user's profile.
+    pub login_shell: bool,
+    /// Extra environment variables; later entries override earlier ones.
+    pub env: Vec<(String, String)>,
+    /// Start from an empty environment with `ISOLATED_PATH` rather than inheriting
+    /// the environment of `cfb`; `env` is still applied.
+    pub isolated_env: bool,
+    /// Start from an environment with only these variables of that of `cfb`; `env` is
+    /// still applied. `isolated_env` takes precedence.
+    pub env_allowlist: Option<Vec<String>>,
+    /// Limit the address space of the command to this many bytes (Unix only).
+    pub memory_limit: Option<u64>,
+    /// Return the output of a command that exited unsuccessfully instead of failing.
+    pub allow_failure: bool,
+    /// For the build that `CodeRunner::run` does first.
+    pub build: BuildOptions,
+    /// Do not print the command before running it, nor draw the progress indicator.
+    pub quiet: bool,
+    /// The program reads the terminal of `cfb`, which the progress indicator would
+    /// draw over.
+    pub stdin_terminal: bool,
+    /// Explanations of exit codes, keyed by the code, for the failure message.
+    pub exit_codes: HashMap<String, String>,
+    /// A named pipe connected to the program's stdin instead of the given input.
+    pub stdin_fifo: Option<PathBuf>,
+    /// A named pipe receiving the program's stdout, which is then not captured.
+    pub stdout_fifo: Option<PathBuf>,
+    /// Arguments for the program after the `run_args` of its language.
+    pub args: Vec<String>,
+    /// The working directory of the command, instead of the current one.
+    pub current_dir: Option<PathBuf>,
+    /// Kill the command once its stdout passes this many bytes.
+    pub output_limit: Option<u64>,
+}
+
+/// Raw bytes written by a command, captured separately per stream.
+#[derive(Debug)]
+pub struct CommandOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// `None` if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    /// Peak resident memory in bytes, where the platform reports it.
+    pub peak_memory: Option<u64>,
+}
+
+impl CommandOutput {
+    /// Stdout followed by stderr.
+    pub fn combined(&self) -> Vec<u8> {
+        [&self.stdout[..], &self.stderr[..]].concat()
+    }
+}
+
+/// The error of a command that exited unsuccessfully, so that its exit code can be
+/// recovered with `downcast_ref`.
+#[derive(Debug)]
+pub struct CommandFailed {
+    /// `None` if the command was killed by a signal.
+    pub exit_code: Option<i32>,
+    message: String,
+}
+
+impl std::fmt::Display for CommandFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CommandFailed {}
+
+/// The error of a command stopped by its `timeout`, told apart from other failures
+/// by `downcast_ref`.
+#[derive(Debug)]
+pub struct CommandTimedOut {
+    /// What the command printed before it was stopped.
+    pub output: CommandOutput,
+    message: String,
+}
+
+impl std::fmt::Display for CommandTimedOut {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CommandTimedOut {}
+
+/// The error of a command killed for printing more than its `output_limit`.
+#[derive(Debug)]
+pub struct CommandOutputExceeded {
+    pub limit: u64,
+    message: String,
+}
+
+impl std::fmt::Display for CommandOutputExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CommandOutputExceeded {}
+
+pub struct RunOutput {
+    pub output: CommandOutput,
+    pub elapsed: Duration,
+}
+
+/// Values substituted for the path placeholders, before any shell quoting.
+/// `{source_abs}` and `{output_abs}` are absolute even where `{output}` is relative to
+/// the current directory.
+fn path_placeholders<'a>(source: &'a Path, output: &'a Path) -> Vec<(&'static str, Cow<'a, str>)> {
+    let absolute = |path: &Path| {
+        let path = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        Cow::Owned(path.to_string_lossy().into_owned())
+    };
+    vec![
+        ("source", source.to_string_lossy()),
+        ("output", output.to_string_lossy()),
+        ("source_abs", absolute(source)),
+        ("output_abs", absolute(output)),
+        (
+            "source_dir",
+            source
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .to_string_lossy(),
+        ),
+        (
+            "stem",
+            source.file_stem().unwrap_or_default().to_string_lossy(),
+        ),
+    ]
+}
+
+fn format_template(template: &str, format_args: &HashMap<String, String>) -> Result<String> {
     SimpleCurlyFormat
-        .format(command, &format_args)
+        .format(template, format_args)
         .map_err(|e| {
             eprintln!("{}", e);
             Error::msg("Invalid format string")
@@ -50,168 +517,2263 @@ fn format_command(command: &str, source: &Path, output: &Path) -> Result<String>
         .map(Cow::into_owned)
 }
 
+/// Placeholder values for a command: each placeholder shell-quoted, plus an
+/// `_unquoted` variant. With `quote` unset, both are substituted as is.
+///
+/// Commands of a language also get `{config_dir}` and, if configured, `{runtime}`,
+/// which is a command prefix and therefore never quoted.
+fn command_args(
+    source: &Path,
+    output: &Path,
+    lang: Option<&LanguageConfig>,
+    quote: bool,
+) -> HashMap<String, String> {
+    let mut format_args = HashMap::new();
+    let mut placeholders = path_placeholders(source, output);
+    if let Some(lang) = lang {
+        placeholders.push(("config_dir", lang.config_dir.to_string_lossy()));
+        if let Some(runtime) = &lang.runtime {
+            format_args.insert("runtime".to_string(), runtime.clone());
+        }
+    }
+    for (name, value) in placeholders {
+        let quoted = if quote {
+            sh::quote(value.as_ref()).to_string_lossy().into_owned()
+        } else {
+            value.to_string()
+        };
+        format_args.insert(name.to_string(), quoted);
+        format_args.insert(format!("{}_unquoted", name), value.into_owned());
+    }
+    format_args
+}
+
+fn format_command(
+    command: &str,
+    source: &Path,
+    output: &Path,
+    lang: Option<&LanguageConfig>,
+) -> Result<String> {
+    format_template(command, &command_args(source, output, lang, true))
+}
+
+/// An argument vector as an equivalent shell command, for display.
+fn join_argv(argv: &[String]) -> String {
+    argv.iter()
+        .map(|arg| sh::quote(arg.as_str()).to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Like `format_command`, but for file paths: placeholders are substituted without quoting.
+fn format_path(path: &str, source: &Path, output: &Path) -> Result<PathBuf> {
+    let format_args = path_placeholders(source, output)
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.into_owned()))
+        .collect();
+    format_template(path, &format_args).map(PathBuf::from)
+}
+
+/// Resolve the `base` chain of a raw language table, with the entry's own keys
+/// overriding the inherited ones.
+fn resolve_lang(
+    name: &str,
+    raw_langs: &toml::value::Table,
+    chain: &mut Vec<String>,
+) -> Result<toml::value::Table> {
+    let lang = raw_langs
+        .get(name)
+        .with_context(|| format!("No language config named {}", name))?
+        .as_table()
+        .with_context(|| format!("Language config {} is not a table", name))?;
+    let mut resolved = match lang.get("base") {
+        Some(base) => {
+            let base = base
+                .as_str()
+                .with_context(|| format!("The base of language config {} is not a string", name))?;
+            chain.push(name.to_string());
+            if chain.iter().any(|seen| seen == base) {
+                bail!(
+                    "Cyclic base in language configs: {} -> {}",
+                    chain.join(" -> "),
+                    base
+                );
+            }
+            let resolved = resolve_lang(base, raw_langs, chain)?;
+            chain.pop();
+            resolved
+        }
+        None => toml::value::Table::new(),
+    };
+    for (key, value) in lang {
+        if key != "base" {
+            resolved.insert(key.clone(), value.clone());
+        }
+    }
+    Ok(resolved)
+}
+
+/// The personal config file, read after every `cfb.toml` from the current directory
+/// up and so overridden by all of them: `$XDG_CONFIG_HOME/cfb/cfb.toml` on Linux, and
+/// the platform's config directory elsewhere.
+fn global_config_path() -> Option<PathBuf> {
+    Some(
+        ProjectDirs::from("", "", "cfb")?
+            .config_dir()
+            .join("cfb.toml"),
+    )
+}
+
+/// Merge the config files, nearer ones taking precedence: every `cfb.toml` from the
+/// current directory up to the root, then the global one.
 pub fn load_config() -> Result<Config> {
-    let mut configs = HashMap::new();
+    let mut raw_langs = toml::value::Table::new();
+    let mut lang_dirs = HashMap::new();
     let mut default_stdin = None;
+    let mut default_stdin_cmd = None;
+    let mut default_command = None;
+    let mut require_default_stdin = None;
+    let mut output_naming = None;
+    let mut case_sensitive_extensions = None;
+    let mut shebang = HashMap::new();
+    let mut checker = None;
+    let mut login_shell = None;
+    let mut sandbox = None;
+    let mut env_file = None;
+    let mut isolated_env = None;
+    let mut env_allowlist = None;
+    let mut compilers = HashMap::new();
+    let mut default_compiler = None;
+    let mut source_encoding = None;
+    let mut allow_source_directives = None;
+    let mut submit = None;
+    let mut runtime = None;
     let dir = std::env::current_dir()?.canonicalize()?;
 
-    for p in dir.ancestors() {
-        let config_path = p.join("cfb.toml");
+    let mut config_paths: Vec<_> = dir.ancestors().map(|p| p.join("cfb.toml")).collect();
+    if let Some(global) = global_config_path() {
+        if !config_paths.contains(&global) {
+            config_paths.push(global);
+        }
+    }
+    for config_path in config_paths {
+        let p = config_path.parent().unwrap_or(&dir);
         if config_path.exists() {
-            let current_config = toml::from_str::<Config>(
-                &std::fs::read_to_string(&config_path)?,
-            )
-            .with_context(|| {
+            let parse_context = || {
                 format!(
                     "Failed to parse config file: {}",
                     config_path.to_string_lossy()
                 )
-            })?;
-            for (language, config) in current_config.langs {
-                configs.entry(language).or_insert(config);
+            };
+            let mut current_config =
+                toml::from_str::<toml::value::Table>(&std::fs::read_to_string(&config_path)?)
+                    .with_context(parse_context)?;
+            // Languages are kept as raw tables until every file is read, so that an
+            // entry can inherit from a `base` defined in another config file.
+            if let Some(langs) = current_config.remove("langs") {
+                let langs = match langs {
+                    toml::Value::Table(langs) => langs,
+                    _ => bail!("{}: `langs` is not a table", parse_context()),
+                };
+                for (language, config) in langs {
+                    if !raw_langs.contains_key(&language) {
+                        lang_dirs.insert(language.clone(), p.to_path_buf());
+                        raw_langs.insert(language, config);
+                    }
+                }
             }
+            let current_config = toml::Value::Table(current_config)
+                .try_into::<Config>()
+                .with_context(parse_context)?;
             if let Some(stdin) = current_config.default_stdin {
                 default_stdin.get_or_insert(stdin);
             }
+            if let Some(command) = current_config.default_stdin_cmd {
+                default_stdin_cmd.get_or_insert(command);
+            }
+            if let Some(command) = current_config.default_command {
+                default_command.get_or_insert(command);
+            }
+            if let Some(require) = current_config.require_default_stdin {
+                require_default_stdin.get_or_insert(require);
+            }
+            if let Some(naming) = current_config.output_naming {
+                output_naming.get_or_insert(naming);
+            }
+            if let Some(case_sensitive) = current_config.case_sensitive_extensions {
+                case_sensitive_extensions.get_or_insert(case_sensitive);
+            }
+            for (interpreter, language) in current_config.shebang {
+                shebang.entry(interpreter).or_insert(language);
+            }
+            if let Some(current_checker) = current_config.checker {
+                checker.get_or_insert(current_checker);
+            }
+            if let Some(login) = current_config.login_shell {
+                login_shell.get_or_insert(login);
+            }
+            if let Some(current_sandbox) = current_config.sandbox {
+                sandbox.get_or_insert(current_sandbox);
+            }
+            if let Some(file) = current_config.env_file {
+                env_file.get_or_insert(file);
+            }
+            if let Some(isolated) = current_config.isolated_env {
+                isolated_env.get_or_insert(isolated);
+            }
+            if let Some(allowlist) = current_config.env_allowlist {
+                env_allowlist.get_or_insert(allowlist);
+            }
+            for (name, profile) in current_config.compilers {
+                compilers.entry(name).or_insert(profile);
+            }
+            if let Some(name) = current_config.default_compiler {
+                default_compiler.get_or_insert(name);
+            }
+            if let Some(label) = current_config.source_encoding {
+                source_encoding.get_or_insert(label);
+            }
+            if let Some(allow) = current_config.allow_source_directives {
+                allow_source_directives.get_or_insert(allow);
+            }
+            if let Some(current_submit) = current_config.submit {
+                submit.get_or_insert(current_submit);
+            }
+            if let Some(current_runtime) = current_config.runtime {
+                runtime.get_or_insert(current_runtime);
+            }
+        }
+    }
+
+    if default_stdin.is_some() && default_stdin_cmd.is_some() {
+        bail!("Only one of default_stdin and default_stdin_cmd can be configured");
+    }
+    if let Some(label) = &source_encoding {
+        self::source_encoding(label)?;
+    }
+
+    let mut configs = HashMap::new();
+    for language in raw_langs.keys() {
+        // Names starting with an underscore are only bases for other entries.
+        if language.starts_with('_') {
+            continue;
+        }
+        let resolved = resolve_lang(language, &raw_langs, &mut Vec::new())?;
+        let mut config = toml::Value::Table(resolved)
+            .try_into::<LanguageConfig>()
+            .with_context(|| format!("Invalid language config: {}", language))?;
+        for pattern in &config.match_glob {
+            Pattern::new(pattern.strip_prefix('!').unwrap_or(pattern)).with_context(|| {
+                format!("Invalid match_glob pattern of {}: {}", language, pattern)
+            })?;
         }
+        config.config_dir = lang_dirs[language].clone();
+        config.login_shell = login_shell.unwrap_or(false);
+        config.sandbox = config.sandbox.or(sandbox);
+        config.runtime = config.runtime.or_else(|| runtime.clone());
+        configs.insert(language.clone(), config);
     }
 
     Ok(Config {
         langs: configs,
         default_stdin,
+        default_stdin_cmd,
+        default_command,
+        require_default_stdin,
+        output_naming,
+        case_sensitive_extensions,
+        shebang,
+        checker,
+        login_shell,
+        sandbox,
+        env_file,
+        isolated_env,
+        env_allowlist,
+        compilers,
+        default_compiler,
+        source_encoding,
+        allow_source_directives,
+        submit,
+        runtime,
+        output_prefix: None,
+        quiet: false,
     })
 }
 
-fn run_command(command: &str, stdin: Option<impl io::Read>) -> Result<String> {
-    println!(
-        "{} {}",
-        sym::COMMAND.bright_white().bold(),
-        command.bright_black()
-    );
-    let output = if let Some(mut stdin) = stdin {
-        let mut child = Command::new("/bin/sh")
-            .arg("-c")
-            .arg(&command)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        let mut child_stdin = child.stdin.take().context("Failed to open command stdin")?;
-        io::copy(&mut stdin, &mut child_stdin)?;
-        drop(child_stdin);
-        child.wait_with_output()?
-    } else {
-        std::process::Command::new("/bin/sh")
-            .arg("-c")
-            .arg(&command)
-            .output()
-            .context("Command execution failed")?
-    };
-    if !output.status.success() {
-        io::stdout().write_all(&output.stdout)?;
-        io::stderr().write_all(&output.stderr)?;
-        bail!(
-            "Command failed: `{}` (exit code {:?})",
-            command,
-            output.status.code()
-        );
+/// The interpreter named by a `#!` line, e.g. `python3` for both
+/// `#!/usr/bin/python3` and `#!/usr/bin/env python3`.
+fn shebang_interpreter(head: &str) -> Option<String> {
+    let first_line = head.lines().next()?;
+    let mut words = first_line.strip_prefix("#!")?.split_whitespace();
+    let mut program = Path::new(words.next()?).file_name()?.to_str()?;
+    if program == "env" {
+        program = words.find(|word| !word.starts_with('-'))?;
     }
-    Ok(
-        (String::from_utf8_lossy(&output.stdout) + String::from_utf8_lossy(&output.stderr))
-            .to_string(),
-    )
+    Some(program.to_string())
 }
 
-fn is_output_up_to_date(source: &Path, output: &Path) -> bool {
-    if !output.exists() {
-        return false;
-    }
-    source
-        .metadata()
-        .and_then(|source_meta| Ok(source_meta.modified()? <= output.metadata()?.modified()?))
-        .unwrap_or(false)
+/// How many bytes at the start of a source are read for its shebang and directives.
+const SOURCE_HEAD_BYTES: u64 = 16 * 1024;
+
+fn source_encoding(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.as_bytes())
+        .with_context(|| format!("Unknown source encoding: {}", label))
 }
 
-impl LanguageConfig {
-    pub fn format_compile_commands(&self, source: &Path, output: &Path) -> Result<Vec<String>> {
-        self.compile_commands
-            .iter()
-            .map(|cmd| format_command(cmd, source, output))
-            .collect()
+impl Config {
+    /// The start of a source file as text, decoded with `source_encoding` (UTF-8 by
+    /// default, or as given by a byte order mark).
+    fn read_head(&self, source: &Path) -> Option<String> {
+        // Opening a FIFO would block until something writes to it.
+        if !fs::metadata(source).ok()?.is_file() {
+            return None;
+        }
+        let mut head = Vec::new();
+        File::open(source)
+            .ok()?
+            .take(SOURCE_HEAD_BYTES)
+            .read_to_end(&mut head)
+            .ok()?;
+        let encoding = match &self.source_encoding {
+            Some(label) => source_encoding(label).ok()?,
+            None => UTF_8,
+        };
+        Some(encoding.decode(&head).0.into_owned())
     }
 
-    pub fn format_run_command(&self, source: &Path, output: &Path) -> Result<String> {
-        format_command(&self.run_command, source, output)
+    /// Put the artifacts in `prefix` below `cfb-out`, which has to be a relative path
+    /// that stays inside it.
+    pub fn set_output_prefix(&mut self, prefix: PathBuf) -> Result<()> {
+        let inside = prefix
+            .components()
+            .all(|component| matches!(component, std::path::Component::Normal(_)));
+        if !inside || prefix.as_os_str().is_empty() {
+            bail!(
+                "The output prefix {} is not a relative path inside cfb-out",
+                prefix.to_string_lossy()
+            );
+        }
+        self.output_prefix = Some(prefix);
+        Ok(())
     }
 
-    pub fn build(&self, source: &Path, output: &Path) -> Result<()> {
-        if is_output_up_to_date(source, output) {
-            eprintln!("   {}", "skip".yellow().bold());
-            return Ok(());
+    pub fn output_prefix(&self) -> Option<&Path> {
+        self.output_prefix.as_deref()
+    }
+
+    /// Use `label` instead of the configured `source_encoding`.
+    pub fn set_source_encoding(&mut self, label: String) -> Result<()> {
+        source_encoding(&label)?;
+        self.source_encoding = Some(label);
+        Ok(())
+    }
+
+    /// Find the language config for an extension. Unless `case_sensitive_extensions`
+    /// is set, an extension without an exact match is retried in lowercase, so that
+    /// `sol.CPP` resolves like `sol.cpp`.
+    fn lookup_extension(&self, ext: &str) -> Option<(&str, &LanguageConfig)> {
+        let found = self.langs.get_key_value(ext).or_else(|| {
+            if self.case_sensitive_extensions.unwrap_or(false) {
+                None
+            } else {
+                self.langs.get_key_value(&ext.to_lowercase())
+            }
+        });
+        found.map(|(name, config)| (name.as_str(), config))
+    }
+
+    /// Find the language config for a source without an extension from its shebang
+    /// line, through the `[shebang]` table.
+    fn lookup_shebang(&self, source: &Path) -> Option<(&str, &LanguageConfig)> {
+        let language = self
+            .shebang
+            .get(&shebang_interpreter(&self.read_head(source)?)?)?;
+        self.langs
+            .get_key_value(language)
+            .map(|(name, config)| (name.as_str(), config))
+    }
+
+    /// The language config for a source, with the source's own `cfb:` directives
+    /// applied on top if `allow_source_directives` is set.
+    fn lang_config(&self, source: &Path) -> Result<Cow<'_, LanguageConfig>> {
+        let config = self.base_lang_config(source)?;
+        if !self.allow_source_directives.unwrap_or(false) {
+            return Ok(Cow::Borrowed(config));
         }
-        let commands = self.format_compile_commands(source, output)?;
-        for command in commands {
-            run_command(&command, Option::<File>::None)?;
+        let directives = self
+            .read_head(source)
+            .map(|head| directive::read_directives(&head))
+            .unwrap_or_default();
+        if directives.is_empty() {
+            return Ok(Cow::Borrowed(config));
+        }
+        // The compile commands are never sandboxed, and the run command is what the
+        // sandbox wraps, so a sandboxed source must not bring its own.
+        if config.sandbox.is_some() {
+            if let Some(key) = ["compile_commands", "run_command"]
+                .into_iter()
+                .find(|key| directives.contains_key(*key))
+            {
+                bail!(
+                    "The {} directive of {} is refused for a sandboxed language",
+                    key,
+                    source.to_string_lossy()
+                );
+            }
+        }
+        let mut table = match toml::Value::try_from(config)? {
+            toml::Value::Table(table) => table,
+            _ => unreachable!("a language config serializes to a table"),
+        };
+        table.extend(directives);
+        match toml::Value::Table(table).try_into::<LanguageConfig>() {
+            Ok(overridden) => Ok(Cow::Owned(LanguageConfig {
+                config_dir: config.config_dir.clone(),
+                login_shell: config.login_shell,
+                compiler: config.compiler.clone(),
+                ..overridden
+            })),
+            Err(e) => {
+                report::warn(&format!("ignoring directives: {}", e));
+                Ok(Cow::Borrowed(config))
+            }
         }
-        Ok(())
     }
 
-    pub fn run(
+    fn base_lang_config(&self, source: &Path) -> Result<&LanguageConfig> {
+        let (language, config) = match source.extension() {
+            Some(ext) => self
+                .lookup_extension(ext.to_str().context("Invalid extension")?)
+                .with_context(|| {
+                    format!("No language config for extension {}", ext.to_string_lossy())
+                })?,
+            None => self
+                .lookup_shebang(source)
+                .context("No extension on source file and no language for its shebang")?,
+        };
+        if !config.claims(source) {
+            bail!(
+                "{} does not match the match_glob of language {}",
+                source.to_string_lossy(),
+                language
+            );
+        }
+        Ok(config)
+    }
+
+    /// How `source` resolves to a language config, for `cfb which`.
+    pub fn resolve(&self, source: &Path) -> Option<Resolution<'_>> {
+        let (language, config, matched_by) = match source.extension() {
+            Some(ext) => {
+                let ext = ext.to_str()?;
+                let (language, config) = self.lookup_extension(ext)?;
+                let matched_by = if language == ext {
+                    format!("extension .{}", ext)
+                } else {
+                    format!("extension .{} (case-insensitive)", ext)
+                };
+                (language, config, matched_by)
+            }
+            None => {
+                let interpreter = shebang_interpreter(&self.read_head(source)?)?;
+                let (language, config) = self.lookup_shebang(source)?;
+                (
+                    language,
+                    config,
+                    format!("shebang interpreter {}", interpreter),
+                )
+            }
+        };
+        Some(Resolution {
+            language,
+            excluded: !config.claims(source),
+            matched_by,
+            config_file: config.config_file(),
+        })
+    }
+
+    /// The name of the language config that handles `source`, if any.
+    pub fn language(&self, source: &Path) -> Option<&str> {
+        let (language, config) = match source.extension() {
+            Some(ext) => self.lookup_extension(ext.to_str()?)?,
+            None => self.lookup_shebang(source)?,
+        };
+        config.claims(source).then_some(language)
+    }
+
+    /// See [`LanguageConfig::format_run_command`].
+    pub fn format_run_command(
         &self,
         source: &Path,
         output: &Path,
-        stdin: Option<impl io::Read>,
+        args: &[String],
     ) -> Result<String> {
-        let command = self.format_run_command(source, output)?;
-        run_command(&command, stdin)
+        self.lang_config(source)?
+            .format_run_command(source, output, args)
     }
-}
 
-pub trait CodeRunner {
-    fn build(&self, source: &Path, output: &Path) -> Result<()>;
-    fn run(&self, source: &Path, output: &Path, stdin: Option<impl io::Read>) -> Result<String>;
-    fn matches(&self, source: &Path) -> bool;
-}
+    /// See [`LanguageConfig::format_repro_command`].
+    pub fn format_repro_command(
+        &self,
+        source: &Path,
+        output: &Path,
+        input: &Path,
+    ) -> Result<Option<String>> {
+        self.lang_config(source)?
+            .format_repro_command(source, output, input)
+    }
 
-impl CodeRunner for Config {
-    fn build(&self, source: &Path, output: &Path) -> Result<()> {
-        let ext = source.extension().context("No extension on source file")?;
-        let lang_config = self
-            .langs
-            .get(ext.to_str().context("Invalid extension")?)
-            .with_context(|| {
-                format!("No language config for extension {}", ext.to_string_lossy())
-            })?;
-        eprintln!(
-            "{} {} {}",
-            sym::TASK.bright_white().bold(),
-            "build".bright_green().bold(),
-            source.to_string_lossy().bright_blue().bold(),
-        );
-        lang_config.build(source, output)?;
+    /// Start an already built program with piped stdin and stdout, for exchanging
+    /// input and output with it while it runs. Its stderr goes to ours.
+    pub fn spawn_interactive(
+        &self,
+        source: &Path,
+        output: &Path,
+        options: &RunOptions,
+    ) -> Result<Child> {
+        let (mut process, display) =
+            self.lang_config(source)?
+                .run_process(source, output, &options.args)?;
+        if !options.quiet && !self.quiet {
+            print_command(&display);
+        }
+        apply_environment(&mut process, options);
+        process
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit());
+        spawn(&mut process).context("Command execution failed")
+    }
+
+    /// See [`LanguageConfig::shell_script`].
+    pub fn shell_script(&self, source: &Path, output: &Path, args: &[String]) -> Result<String> {
+        self.lang_config(source)?.shell_script(source, output, args)
+    }
+
+    /// Replace the compile commands of the languages a compiler profile applies to.
+    /// Without a name, `default_compiler` is used, if configured.
+    pub fn select_compiler(&mut self, name: Option<&str>) -> Result<()> {
+        let Some(name) = name.or(self.default_compiler.as_deref()) else {
+            return Ok(());
+        };
+        let profile = self
+            .compilers
+            .get(name)
+            .with_context(|| format!("No compiler profile named {}", name))?;
+        if let Some(langs) = &profile.langs {
+            if let Some(unknown) = langs.iter().find(|lang| !self.langs.contains_key(*lang)) {
+                bail!(
+                    "Compiler profile {} names unknown language {}",
+                    name,
+                    unknown
+                );
+            }
+        }
+        for (language, config) in &mut self.langs {
+            let applies = match &profile.langs {
+                Some(langs) => langs.contains(language),
+                None => !config.compile_commands.is_empty(),
+            };
+            if applies {
+                config.compiler = Some(profile.compile_commands.clone());
+            }
+        }
         Ok(())
     }
-    fn run(&self, source: &Path, output: &Path, stdin: Option<impl io::Read>) -> Result<String> {
-        let ext = source.extension().context("No extension on source file")?;
-        let lang_config = self
-            .langs
-            .get(ext.to_str().context("Invalid extension")?)
-            .with_context(|| {
-                format!("No language config for extension {}", ext.to_string_lossy())
-            })?;
-        self.build(source, output)?;
-        eprintln!(
-            "{} {} {}",
-            sym::TASK.bright_white().bold(),
-            "run".bright_green().bold(),
-            source.to_string_lossy().bright_blue().bold(),
-        );
-        lang_config.run(source, output, stdin)
+
+    pub fn langs(&self) -> &LanguageConfigs {
+        &self.langs
     }
 
-    fn matches(&self, source: &Path) -> bool {
-        source.extension().map_or(false, |ext| {
-            self.langs.contains_key(&ext.to_string_lossy().to_string())
+    pub fn default_command(&self) -> DefaultCommand {
+        self.default_command.unwrap_or_default()
+    }
+
+    /// The output checker for a source, from the `[checker]` section and its overrides.
+    pub fn checker_for(&self, source: &Path) -> Result<Checker> {
+        match &self.checker {
+            Some(checker) => checker.resolve(source),
+            None => Ok(Checker::default()),
+        }
+    }
+
+    pub fn login_shell(&self) -> bool {
+        self.login_shell.unwrap_or(false)
+    }
+
+    pub fn isolated_env(&self) -> bool {
+        self.isolated_env.unwrap_or(false)
+    }
+
+    pub fn env_allowlist(&self) -> Vec<String> {
+        match &self.env_allowlist {
+            Some(allowlist) => allowlist.clone(),
+            None => ["PATH", "HOME", "LANG"].map(String::from).to_vec(),
+        }
+    }
+
+    /// Whether the language of a source has no build step, so nothing is written to
+    /// its output path.
+    pub fn interpreted(&self, source: &Path) -> bool {
+        self.lang_config(source)
+            .is_ok_and(|config| config.interpreted())
+    }
+
+    /// The configured `output_ext` of the language of a source.
+    pub fn output_ext(&self, source: &Path) -> Option<String> {
+        self.base_lang_config(source).ok()?.output_ext.clone()
+    }
+
+    pub fn submit(&self) -> Option<&SubmitConfig> {
+        self.submit.as_ref()
+    }
+
+    /// Expand the placeholders of a command configured outside the languages.
+    pub fn format_command(&self, command: &str, source: &Path, output: &Path) -> Result<String> {
+        format_command(command, source, output, None)
+    }
+
+    pub fn output_naming(&self) -> OutputNaming {
+        self.output_naming.unwrap_or_default()
+    }
+
+    /// Start the `default_stdin_cmd` generator for a source file, if one is configured,
+    /// returning a reader over its stdout.
+    pub fn default_stdin_generator(
+        &self,
+        source: &Path,
+        output: &Path,
+    ) -> Result<Option<Generator>> {
+        self.default_stdin_cmd
+            .as_deref()
+            .map(|command| {
+                Generator::spawn(
+                    &format_command(command, source, output, None)?,
+                    self.login_shell(),
+                    self.quiet,
+                )
+            })
+            .transpose()
+    }
+
+    /// Resolve `default_stdin` for a source file, expanding its placeholders.
+    ///
+    /// A resolved path that does not exist yields `None`, unless `require_default_stdin`
+    /// is set, in which case it is an error.
+    pub fn default_stdin(&self, source: &Path, output: &Path) -> Result<Option<PathBuf>> {
+        let Some(template) = &self.default_stdin else {
+            return Ok(None);
+        };
+        let path = format_path(template, source, output)?;
+        if path.exists() {
+            Ok(Some(path))
+        } else if self.require_default_stdin.unwrap_or(false) {
+            bail!("Default stdin file not found: {}", path.to_string_lossy());
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The variables of the configured `env_file` for a source, if it exists.
+    pub fn env(&self, source: &Path, output: &Path) -> Result<Vec<(String, String)>> {
+        let Some(template) = &self.env_file else {
+            return Ok(Vec::new());
+        };
+        let path = format_path(template, source, output)?;
+        if path.exists() {
+            env::load_env_file(&path)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// The run options every run of a source starts from, as `cfb test` applies them:
+    /// the variables of `env_file`, `isolated_env`, and the limits of its `.limits.toml`,
+    /// falling back to the time limit of the `.cfb/problem.toml` next to it.
+    pub fn run_options(&self, source: &Path, output: &Path) -> Result<RunOptions> {
+        let limits = problem::load_limits(source)?;
+        let problem_limit = match source.parent() {
+            Some(dir) => problem::load_problem(dir)?.and_then(|problem| problem.time_limit),
+            None => None,
+        };
+        Ok(RunOptions {
+            env: self.env(source, output)?,
+            isolated_env: self.isolated_env(),
+            timeout: limits.time_limit.or(problem_limit),
+            memory_limit: limits.memory_limit,
+            ..RunOptions::default()
         })
     }
+
+    /// Every language with the config file whose entry won the merge, by name.
+    pub fn language_origins(&self) -> Vec<(&str, PathBuf)> {
+        let mut origins: Vec<_> = self
+            .langs
+            .iter()
+            .map(|(language, config)| (language.as_str(), config.config_file()))
+            .collect();
+        origins.sort();
+        origins
+    }
+
+    /// Make every build and run quiet, as with [`BuildOptions::quiet`].
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// `options`, made quiet by `--quiet`.
+    fn quiet_build_options(&self, options: &BuildOptions) -> BuildOptions {
+        BuildOptions {
+            quiet: options.quiet || self.quiet,
+            ..*options
+        }
+    }
+
+    fn quiet_run_options(&self, options: &RunOptions) -> RunOptions {
+        RunOptions {
+            quiet: options.quiet || self.quiet,
+            build: self.quiet_build_options(&options.build),
+            ..options.clone()
+        }
+    }
+
+    /// Serialize the merged configuration back to TOML.
+    pub fn to_toml(&self) -> Result<String> {
+        // Going through `toml::Value` orders plain values before tables, which the
+        // direct serializer would otherwise reject.
+        Ok(toml::to_string(&toml::Value::try_from(self)?)?)
+    }
+
+    /// A JSON Schema of `cfb.toml`, for editor completion and validation.
+    pub fn schema() -> Result<String> {
+        Ok(serde_json::to_string_pretty(&schemars::schema_for!(
+            Config
+        ))?)
+    }
+}
+
+/// Kill a child spawned in its own process group, along with everything it started.
+fn kill_command(child: &mut Child) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        if signal_command(child, libc::SIGKILL) {
+            return Ok(());
+        }
+    }
+    child.kill()
+}
+
+/// Send a signal to the child's process group. Returns whether that succeeded.
+#[cfg(unix)]
+fn signal_command(child: &Child, signal: libc::c_int) -> bool {
+    // SAFETY: `kill` has no memory safety preconditions.
+    unsafe { libc::kill(-(child.id() as libc::pid_t), signal) == 0 }
+}
+
+/// The process groups of the running commands that have one. They are not in the
+/// terminal's foreground group, so Ctrl-C only reaches them through
+/// [`forward_signal`]. Free slots are zero.
+#[cfg(unix)]
+static PROCESS_GROUPS: [std::sync::atomic::AtomicI32; 64] =
+    [const { std::sync::atomic::AtomicI32::new(0) }; 64];
+
+/// Pass SIGINT or SIGTERM on to the process groups of the running commands, then
+/// terminate `cfb` by it as usual.
+#[cfg(unix)]
+extern "C" fn forward_signal(signal: libc::c_int) {
+    for group in &PROCESS_GROUPS {
+        let pgid = group.load(Ordering::SeqCst);
+        if pgid != 0 {
+            // SAFETY: `kill` is async-signal-safe.
+            unsafe { libc::kill(-pgid, signal) };
+        }
+    }
+    // SAFETY: `signal` and `raise` are async-signal-safe.
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// A process group registered in `PROCESS_GROUPS` until dropped.
+#[cfg(unix)]
+struct ProcessGroup(Option<usize>);
+
+#[cfg(unix)]
+impl ProcessGroup {
+    fn register(child: &Child) -> Self {
+        static HANDLER: std::sync::Once = std::sync::Once::new();
+        HANDLER.call_once(|| {
+            for signal in [libc::SIGINT, libc::SIGTERM] {
+                // SAFETY: the handler only makes async-signal-safe calls.
+                unsafe {
+                    libc::signal(
+                        signal,
+                        forward_signal as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                    )
+                };
+            }
+        });
+        let pgid = child.id() as i32;
+        let slot = PROCESS_GROUPS.iter().position(|group| {
+            group
+                .compare_exchange(0, pgid, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        });
+        ProcessGroup(slot)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(slot) = self.0 {
+            PROCESS_GROUPS[slot].store(0, Ordering::SeqCst);
+        }
+    }
+}
+
+/// The exit status of a finished child, with its peak memory usage in bytes where
+/// the platform reports it.
+type Finished = (ExitStatus, Option<u64>);
+
+/// Reap the child if it has exited (or, with `block`, once it exits).
+///
+/// On Unix this goes through `wait4`, which also reports the peak resident set size
+/// of the child and everything it waited for.
+#[cfg(unix)]
+fn reap(child: &mut Child, block: bool) -> io::Result<Option<Finished>> {
+    use std::os::unix::process::ExitStatusExt;
+    let mut status = 0;
+    // SAFETY: `rusage` is plain data, for which all zeroes is a valid value.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let flags = if block { 0 } else { libc::WNOHANG };
+    loop {
+        // SAFETY: both pointers are valid for the duration of the call.
+        let pid = unsafe { libc::wait4(child.id() as libc::pid_t, &mut status, flags, &mut usage) };
+        match pid {
+            -1 => {
+                let error = io::Error::last_os_error();
+                if error.kind() != io::ErrorKind::Interrupted {
+                    return Err(error);
+                }
+            }
+            0 => return Ok(None),
+            _ => break,
+        }
+    }
+    // Linux reports kilobytes, macOS bytes.
+    let scale = if cfg!(target_os = "macos") { 1 } else { 1024 };
+    let peak_memory = u64::try_from(usage.ru_maxrss).ok().map(|rss| rss * scale);
+    Ok(Some((ExitStatus::from_raw(status), peak_memory)))
+}
+
+#[cfg(not(unix))]
+fn reap(child: &mut Child, block: bool) -> io::Result<Option<Finished>> {
+    let status = if block {
+        Some(child.wait()?)
+    } else {
+        child.try_wait()?
+    };
+    Ok(status.map(|status| (status, None)))
+}
+
+/// Poll the child until it exits, `deadline` passes or `stop` is set.
+fn wait_until(
+    child: &mut Child,
+    deadline: Option<Instant>,
+    stop: &AtomicBool,
+) -> io::Result<Option<Finished>> {
+    loop {
+        if let Some(finished) = reap(child, false)? {
+            return Ok(Some(finished));
+        }
+        if stop.load(Ordering::Relaxed) || deadline.is_some_and(|d| Instant::now() >= d) {
+            return Ok(None);
+        }
+        thread::sleep(WAIT_POLL_INTERVAL);
+    }
+}
+
+/// Wait for the child, stopping it once the timeout has passed or, for a program
+/// over its `output_limit`, once `stop` is set. Returns `None` if it was stopped.
+fn wait_command(
+    child: &mut Child,
+    options: &RunOptions,
+    stop: &AtomicBool,
+) -> io::Result<Option<Finished>> {
+    if options.timeout.is_none() && options.output_limit.is_none() {
+        return reap(child, true);
+    }
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    if let Some(finished) = wait_until(child, deadline, stop)? {
+        return Ok(Some(finished));
+    }
+    #[cfg(unix)]
+    if let (TimeoutSignal::Term, false) = (options.timeout_signal, stop.load(Ordering::Relaxed)) {
+        let grace = Instant::now() + options.timeout_grace;
+        if signal_command(child, libc::SIGTERM)
+            && wait_until(child, Some(grace), &AtomicBool::new(false))?.is_some()
+        {
+            return Ok(None);
+        }
+    }
+    kill_command(child)?;
+    reap(child, true)?;
+    Ok(None)
+}
+
+/// A running generator command. Reading yields its stdout; once that ends, a failed
+/// exit status is reported as a read error.
+pub struct Generator {
+    child: Child,
+    stdout: ChildStdout,
+    command: String,
+}
+
+impl Generator {
+    fn spawn(command: &str, login_shell: bool, quiet: bool) -> Result<Self> {
+        if !quiet {
+            print_command(command);
+        }
+        let mut child = spawn(
+            shell_command(command, login_shell)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped()),
+        )
+        .context("Command execution failed")?;
+        let stdout = child
+            .stdout
+            .take()
+            .context("Failed to open command stdout")?;
+        Ok(Generator {
+            child,
+            stdout,
+            command: command.to_string(),
+        })
+    }
+}
+
+impl Read for Generator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.stdout.read(buf)?;
+        if read == 0 && !buf.is_empty() {
+            let status = self.child.wait()?;
+            if !status.success() {
+                return Err(io::Error::other(format!(
+                    "Generator failed: `{}` (exit code {:?})",
+                    self.command,
+                    status.code()
+                )));
+            }
+        }
+        Ok(read)
+    }
+}
+
+impl Drop for Generator {
+    fn drop(&mut self) {
+        // The solution may exit without reading everything; don't leave the
+        // generator blocked on a full pipe.
+        if let Ok(None) = self.child.try_wait() {
+            let _ = self.child.kill();
+        }
+        let _ = self.child.wait();
+    }
+}
+
+fn shell_command(command: &str, login_shell: bool) -> Command {
+    let mut shell = Command::new("/bin/sh");
+    shell
+        .arg(if login_shell { "-lc" } else { "-c" })
+        .arg(command);
+    shell
+}
+
+#[cfg(not(windows))]
+fn spawn(command: &mut Command) -> io::Result<Child> {
+    command.spawn()
+}
+
+/// Antivirus scanners on Windows briefly lock freshly written binaries, so spawning
+/// is retried a few times before giving up.
+#[cfg(windows)]
+fn spawn(command: &mut Command) -> io::Result<Child> {
+    const ATTEMPTS: u32 = 5;
+    const DELAY: Duration = Duration::from_millis(200);
+    let mut attempt = 1;
+    loop {
+        match command.spawn() {
+            Err(e)
+                if attempt < ATTEMPTS
+                    && matches!(
+                        e.kind(),
+                        io::ErrorKind::PermissionDenied | io::ErrorKind::Other
+                    ) =>
+            {
+                attempt += 1;
+                thread::sleep(DELAY);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Run a shell command, returning what it wrote to stdout and stderr.
+pub fn run_command(
+    command: &str,
+    stdin: Option<impl io::Read + Send>,
+    options: &RunOptions,
+) -> Result<CommandOutput> {
+    run_process(
+        shell_command(command, options.login_shell),
+        command,
+        stdin,
+        options,
+    )
+}
+
+/// Print a command about to run, as the `$` line.
+fn print_command(command: &str) {
+    println!(
+        "{} {}",
+        sym::COMMAND.bright_white().bold(),
+        command.bright_black()
+    );
+}
+
+/// Set up the environment, working directory and resource limits of a program from
+/// its run options.
+fn apply_environment(process: &mut Command, options: &RunOptions) {
+    if let Some(dir) = &options.current_dir {
+        process.current_dir(dir);
+    }
+    if options.isolated_env {
+        process.env_clear().env("PATH", ISOLATED_PATH);
+    } else if let Some(allowlist) = &options.env_allowlist {
+        process.env_clear();
+        for name in allowlist {
+            if let Some(value) = std::env::var_os(name) {
+                process.env(name, value);
+            }
+        }
+    }
+    process.envs(options.env.iter().map(|(key, value)| (key, value)));
+    #[cfg(unix)]
+    if let Some(limit) = options.memory_limit {
+        use std::os::unix::process::CommandExt;
+        let limit = libc::rlimit {
+            rlim_cur: limit as libc::rlim_t,
+            rlim_max: limit as libc::rlim_t,
+        };
+        // SAFETY: the hook only calls `setrlimit`, which is async-signal-safe.
+        unsafe {
+            process.pre_exec(move || {
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) == 0 {
+                    Ok(())
+                } else {
+                    Err(io::Error::last_os_error())
+                }
+            });
+        }
+    }
+}
+
+/// Open the named pipes of `RunOptions::stdin_fifo` and `stdout_fifo`. Opening a pipe
+/// waits for the other side to open it too, so both are opened at the same time:
+/// whichever order the peer opens them in cannot deadlock.
+fn open_fifos(options: &RunOptions) -> Result<(Option<File>, Option<File>)> {
+    thread::scope(|scope| {
+        let stdin = options
+            .stdin_fifo
+            .as_deref()
+            .map(|path| scope.spawn(move || open_fifo(path, false)));
+        let stdout = options
+            .stdout_fifo
+            .as_deref()
+            .map(|path| scope.spawn(move || open_fifo(path, true)));
+        let join = |handle: Option<thread::ScopedJoinHandle<'_, Result<File>>>| {
+            handle
+                .map(|handle| handle.join().expect("opening a named pipe panicked"))
+                .transpose()
+        };
+        Ok((join(stdin)?, join(stdout)?))
+    })
+}
+
+#[cfg(unix)]
+fn open_fifo(path: &Path, write: bool) -> Result<File> {
+    use std::os::unix::fs::FileTypeExt;
+    let is_fifo = fs::metadata(path)
+        .map(|meta| meta.file_type().is_fifo())
+        .unwrap_or(false);
+    if !is_fifo {
+        bail!("Not a named pipe: {}", path.to_string_lossy());
+    }
+    fs::OpenOptions::new()
+        .read(!write)
+        .write(write)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.to_string_lossy()))
+}
+
+#[cfg(not(unix))]
+fn open_fifo(path: &Path, _write: bool) -> Result<File> {
+    bail!(
+        "Named pipes such as {} are only supported on Unix",
+        path.to_string_lossy()
+    );
+}
+
+/// Run a process, echoing it as `command`.
+fn run_process(
+    mut shell: Command,
+    command: &str,
+    stdin: Option<impl io::Read + Send>,
+    options: &RunOptions,
+) -> Result<CommandOutput> {
+    if !options.quiet {
+        print_command(command);
+    }
+    apply_environment(&mut shell, options);
+    let (stdin_fifo, stdout_fifo) = open_fifos(options)?;
+    shell
+        .stdin(match stdin_fifo {
+            Some(fifo) => Stdio::from(fifo),
+            None if stdin.is_some() => Stdio::piped(),
+            None => Stdio::null(),
+        })
+        .stdout(match stdout_fifo {
+            Some(fifo) => Stdio::from(fifo),
+            None => Stdio::piped(),
+        })
+        .stderr(Stdio::piped());
+    #[cfg(unix)]
+    let own_group = options.timeout.is_some() || options.output_limit.is_some();
+    #[cfg(unix)]
+    if own_group {
+        // A separate process group lets a timeout kill the programs the shell started too.
+        use std::os::unix::process::CommandExt;
+        shell.process_group(0);
+    }
+    let mut child = spawn(&mut shell).context("Command execution failed")?;
+    #[cfg(unix)]
+    let _group = own_group.then(|| ProcessGroup::register(&child));
+
+    // The pipes are drained on their own threads, so whatever the command printed
+    // is still available if it has to be killed.
+    let exceeded = AtomicBool::new(false);
+    let (status, stdout, stderr) = thread::scope(|scope| -> Result<_> {
+        let child_stdin = child.stdin.take();
+        let writer = stdin.zip(child_stdin).map(|(mut stdin, mut child_stdin)| {
+            scope.spawn(move || match io::copy(&mut stdin, &mut child_stdin) {
+                Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+                _ => Ok(()),
+            })
+        });
+        // Without a pipe, stdout goes to `RunOptions::stdout_fifo` and nothing is captured.
+        let child_stdout = child.stdout.take();
+        let exceeded = &exceeded;
+        let mut child_stderr = child
+            .stderr
+            .take()
+            .context("Failed to open command stderr")?;
+        let stdout = scope.spawn(move || -> io::Result<_> {
+            let mut buf = Vec::new();
+            let Some(child_stdout) = child_stdout else {
+                return Ok(buf);
+            };
+            // Reading stops one byte past the limit, and the program is then killed.
+            let limit = options.output_limit;
+            child_stdout
+                .take(limit.map_or(u64::MAX, |limit| limit.saturating_add(1)))
+                .read_to_end(&mut buf)?;
+            if limit.is_some_and(|limit| buf.len() as u64 > limit) {
+                exceeded.store(true, Ordering::Relaxed);
+            }
+            Ok(buf)
+        });
+        let stderr = scope.spawn(move || {
+            let mut buf = Vec::new();
+            child_stderr.read_to_end(&mut buf).map(|_| buf)
+        });
+        let (stop_heartbeat, heartbeat_stopped) = mpsc::channel();
+        if !options.quiet && !options.stdin_terminal {
+            scope.spawn(move || progress::heartbeat(heartbeat_stopped));
+        }
+        let status = wait_command(&mut child, options, exceeded);
+        drop(stop_heartbeat);
+        let status = status?;
+        let stdout = stdout.join().expect("stdout reader panicked")?;
+        let stderr = stderr.join().expect("stderr reader panicked")?;
+        if let Some(writer) = writer {
+            writer.join().expect("stdin writer panicked")?;
+        }
+        Ok((status, stdout, stderr))
+    })?;
+
+    if exceeded.into_inner() {
+        io::stderr().write_all(&stderr)?;
+        let limit = options.output_limit.unwrap_or_default();
+        return Err(CommandOutputExceeded {
+            limit,
+            message: format!("Command printed more than {} bytes: `{}`", limit, command),
+        }
+        .into());
+    }
+    let Some((status, peak_memory)) = status else {
+        return Err(CommandTimedOut {
+            output: CommandOutput {
+                stdout,
+                stderr,
+                exit_code: None,
+                peak_memory: None,
+            },
+            message: format!(
+                "Command timed out after {} ms: `{}`",
+                options.timeout.unwrap_or_default().as_millis(),
+                command
+            ),
+        }
+        .into());
+    };
+    if !status.success() && !options.allow_failure {
+        io::stdout().write_all(&stdout)?;
+        io::stderr().write_all(&stderr)?;
+        let meaning = status
+            .code()
+            .and_then(|code| options.exit_codes.get(&code.to_string()));
+        let message = match meaning {
+            Some(meaning) => format!(
+                "Command failed: `{}` (exit code {:?}: {})",
+                command,
+                status.code(),
+                meaning
+            ),
+            None => format!(
+                "Command failed: `{}` (exit code {:?})",
+                command,
+                status.code()
+            ),
+        };
+        return Err(CommandFailed {
+            exit_code: status.code(),
+            message,
+        }
+        .into());
+    }
+    if options.fail_on_stderr && !stderr.trim_ascii().is_empty() {
+        io::stdout().write_all(&stdout)?;
+        io::stderr().write_all(&stderr)?;
+        bail!("Command wrote to stderr: `{}`", command);
+    }
+    Ok(CommandOutput {
+        stdout,
+        stderr,
+        exit_code: status.code(),
+        peak_memory,
+    })
+}
+
+/// Fail if the compile commands succeeded without producing the output file.
+fn verify_output(output: &Path) -> Result<()> {
+    match output.metadata() {
+        Ok(meta) if meta.is_dir() => {
+            if fs::read_dir(output)?.next().is_none() {
+                bail!(
+                    "Compilation succeeded but {} is empty",
+                    output.to_string_lossy()
+                );
+            }
+            Ok(())
+        }
+        Ok(meta) if meta.len() > 0 => Ok(()),
+        Ok(_) => bail!(
+            "Compilation succeeded but {} is empty",
+            output.to_string_lossy()
+        ),
+        Err(_) => bail!(
+            "Compilation succeeded but did not produce {}",
+            output.to_string_lossy()
+        ),
+    }
+}
+
+/// Sidecar file next to the output recording the hash of the commands that built it.
+fn commands_hash_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".cfb-hash");
+    PathBuf::from(path)
+}
+
+/// Where the accepted test cases of an artifact are remembered.
+pub fn results_cache_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".cfb-results");
+    PathBuf::from(path)
+}
+
+/// A coarse human-readable age, such as `42s`, `3m` or `2d`.
+fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        3600..=86399 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+/// Delete a build artifact and its sidecar files, if they exist.
+pub fn remove_artifact(output: &Path) -> Result<()> {
+    for path in [
+        output.to_path_buf(),
+        commands_hash_path(output),
+        results_cache_path(output),
+    ] {
+        let removed = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match removed {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(e)
+                    .with_context(|| format!("Failed to remove {}", path.to_string_lossy()))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check that a freshly built output is not older than its source.
+fn verify_output_newer(source: &Path, output: &Path) -> Result<()> {
+    let source_modified = source.metadata()?.modified()?;
+    match newest_modified(output) {
+        Some(output_modified) if output_modified >= source_modified => Ok(()),
+        Some(_) => bail!(
+            "Compilation succeeded but {} is older than {}",
+            output.to_string_lossy(),
+            source.to_string_lossy()
+        ),
+        None => bail!(
+            "Compilation succeeded but did not produce {}",
+            output.to_string_lossy()
+        ),
+    }
+}
+
+/// The modification time of a file, or of the newest file below a directory.
+fn newest_modified(path: &Path) -> Option<SystemTime> {
+    let meta = path.metadata().ok()?;
+    if !meta.is_dir() {
+        return meta.modified().ok();
+    }
+    fs::read_dir(path)
+        .ok()?
+        .filter_map(|entry| newest_modified(&entry.ok()?.path()))
+        .max()
+        .or_else(|| meta.modified().ok())
+}
+
+/// The contents of an artifact; for a directory artifact, those of all files below
+/// it in path order.
+pub fn read_artifact(output: &Path) -> io::Result<Vec<u8>> {
+    if !output.is_dir() {
+        return fs::read(output);
+    }
+    let mut entries = fs::read_dir(output)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    let mut contents = Vec::new();
+    for entry in entries {
+        contents.extend(entry.to_string_lossy().as_bytes());
+        contents.extend(read_artifact(&entry)?);
+    }
+    Ok(contents)
+}
+
+/// Fail when `output` resolves to the source itself, which a build or clean would
+/// overwrite.
+pub fn check_output_path(source: &Path, output: &Path) -> Result<()> {
+    if let (Ok(source), Ok(resolved)) = (source.canonicalize(), output.canonicalize()) {
+        if source == resolved {
+            bail!(
+                "The build output {} is the source file itself",
+                output.to_string_lossy()
+            );
+        }
+    }
+    Ok(())
+}
+
+static KEEP_TEMPFILES: AtomicBool = AtomicBool::new(false);
+
+/// Keep the temporary files and directories of builds for debugging, printing their
+/// paths instead of removing them.
+pub fn set_keep_tempfiles(keep: bool) {
+    KEEP_TEMPFILES.store(keep, Ordering::Relaxed);
+}
+
+/// A directory for a `use_tempdir` build, removed again when dropped, whether or not
+/// the build succeeded, unless `set_keep_tempfiles` was called.
+struct BuildDir(PathBuf);
+
+impl BuildDir {
+    /// Create a directory that no other build uses. A name taken by a leftover
+    /// directory or a concurrent build is skipped for the next one.
+    fn create() -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .subsec_nanos();
+        let mut attempt = 0;
+        loop {
+            let name = format!("cfb-build-{}-{}-{}", std::process::id(), nanos, attempt);
+            let path = std::env::temp_dir().join(name);
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(BuildDir(path)),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Failed to create {}", path.to_string_lossy()))
+                }
+            }
+        }
+    }
+}
+
+impl Drop for BuildDir {
+    fn drop(&mut self) {
+        if KEEP_TEMPFILES.load(Ordering::Relaxed) {
+            eprintln!(
+                "   {} {}",
+                "kept".yellow().bold(),
+                self.0.to_string_lossy().bright_black()
+            );
+            return;
+        }
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Copy an artifact built elsewhere over `output`; a directory artifact is copied
+/// with everything in it.
+fn copy_artifact(built: &Path, output: &Path) -> Result<()> {
+    if !built.is_dir() {
+        fs::copy(built, output).with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                built.to_string_lossy(),
+                output.to_string_lossy()
+            )
+        })?;
+        return Ok(());
+    }
+    if output.exists() {
+        fs::remove_dir_all(output)?;
+    }
+    fs::create_dir_all(output)?;
+    for entry in fs::read_dir(built)? {
+        let entry = entry?;
+        copy_artifact(&entry.path(), &output.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Remove the symbols from a freshly built artifact.
+fn strip_artifact(output: &Path, options: &RunOptions) -> Result<()> {
+    if !cfg!(unix) {
+        return Ok(());
+    }
+    if output.is_dir() {
+        report::warn("not stripping a directory output");
+        return Ok(());
+    }
+    if doctor::find_program("strip").is_none() {
+        report::warn("strip is not installed; the artifact is left as is");
+        return Ok(());
+    }
+    let command = format!("strip {}", sh::quote(output.as_os_str()).to_string_lossy());
+    run_command(&command, Option::<File>::None, options)?;
+    Ok(())
+}
+
+/// Print the inputs of every up-to-date check, see [`set_explain`].
+static EXPLAIN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_explain(explain: bool) {
+    EXPLAIN.store(explain, Ordering::Relaxed);
+}
+
+/// The inputs of the check whether an artifact is up to date.
+struct Freshness {
+    source_modified: Option<SystemTime>,
+    output_modified: Option<SystemTime>,
+    stored_hash: Option<String>,
+    commands_hash: String,
+    no_skip: bool,
+}
+
+impl Freshness {
+    fn check(source: &Path, output: &Path, commands_hash: &str, options: &BuildOptions) -> Self {
+        Freshness {
+            source_modified: source.metadata().and_then(|meta| meta.modified()).ok(),
+            output_modified: newest_modified(output),
+            stored_hash: fs::read_to_string(commands_hash_path(output))
+                .ok()
+                .map(|stored| stored.trim().to_string()),
+            commands_hash: commands_hash.to_string(),
+            no_skip: options.no_skip,
+        }
+    }
+
+    /// Why the artifact has to be rebuilt, or `None` if it is up to date.
+    fn stale_reason(&self) -> Option<&'static str> {
+        if self.no_skip {
+            return Some("--no-skip was given");
+        }
+        let Some(output_modified) = self.output_modified else {
+            return Some("the output does not exist");
+        };
+        match &self.stored_hash {
+            None => return Some("no command hash was stored"),
+            Some(stored) if *stored != self.commands_hash => {
+                return Some("the compile commands changed")
+            }
+            _ => {}
+        }
+        match self.source_modified {
+            None => Some("the source mtime is unknown"),
+            Some(source_modified) if source_modified > output_modified => {
+                Some("the source is newer than the output")
+            }
+            _ => None,
+        }
+    }
+
+    fn explain(&self) {
+        let time = |time: Option<SystemTime>| match time.map(|time| time.elapsed()) {
+            Some(Ok(age)) => format!("{} ago", format_age(age)),
+            Some(Err(_)) => "in the future".to_string(),
+            None => "none".to_string(),
+        };
+        let short_hash = |hash: &str| hash.chars().take(12).collect::<String>();
+        let lines = [
+            ("source mtime", time(self.source_modified)),
+            ("output mtime", time(self.output_modified)),
+            ("command hash", short_hash(&self.commands_hash)),
+            (
+                "stored hash",
+                self.stored_hash
+                    .as_deref()
+                    .map_or("none".to_string(), short_hash),
+            ),
+        ];
+        for (name, value) in lines {
+            eprintln!(
+                "   {} {}",
+                format!("{:<12}", name).yellow(),
+                value.bright_black()
+            );
+        }
+        let conclusion = match self.stale_reason() {
+            Some(reason) => format!("rebuild: {}", reason),
+            None => "up to date".to_string(),
+        };
+        eprintln!("   {} {}", "=>".yellow().bold(), conclusion.bright_black());
+    }
+}
+
+/// Shell builtins that run the command following them.
+const PREFIX_BUILTINS: &[&str] = &["exec", "command"];
+
+/// Shell builtins that can start a command, which are not looked up on `PATH`.
+const SHELL_BUILTINS: &[&str] = &[
+    ".", ":", "alias", "cd", "eval", "export", "set", "shift", "trap", "ulimit", "umask", "unset",
+    "wait",
+];
+
+/// The program a shell command starts: its first word after any `NAME=value`
+/// assignments and builtins such as `exec`. `None` for a command starting with
+/// another builtin, such as `cd`, or one that cannot be split into words.
+fn command_program(command: &str) -> Option<String> {
+    let is_assignment = |word: &str| {
+        word.split_once('=').is_some_and(|(name, _)| {
+            name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+    };
+    let program = words::split_words(command)
+        .ok()?
+        .into_iter()
+        .find(|word| !is_assignment(word) && !PREFIX_BUILTINS.contains(&word.as_str()))?;
+    (!SHELL_BUILTINS.contains(&program.as_str())).then_some(program)
+}
+
+impl LanguageConfig {
+    /// The compile commands of the selected compiler profile, or the configured ones.
+    /// An interpreted language has none.
+    fn compile_steps(&self) -> &[CompileStep] {
+        if self.interpreted() {
+            return &[];
+        }
+        self.compiler.as_deref().unwrap_or(&self.compile_commands)
+    }
+
+    fn interpreted(&self) -> bool {
+        self.interpreted.unwrap_or(false)
+    }
+
+    /// The `cfb.toml` defining this language.
+    fn config_file(&self) -> PathBuf {
+        self.config_dir.join("cfb.toml")
+    }
+
+    /// What `{output}` stands for in the run command.
+    fn run_target<'a>(&self, source: &'a Path, output: &'a Path) -> &'a Path {
+        if self.interpreted() {
+            source
+        } else {
+            output
+        }
+    }
+
+    /// The programs invoked by the compile and run commands, see [`command_program`].
+    ///
+    /// `{runtime}` and `{config_dir}` are substituted first. Programs still containing
+    /// placeholders (such as a `{output}` run command) are built artifacts rather than
+    /// installed tools, so they are left out. A configured sandbox tool is included
+    /// as well.
+    pub fn programs(&self) -> Vec<String> {
+        let mut programs = Vec::new();
+        let compile_commands = self.compile_steps().iter().flat_map(CompileStep::commands);
+        for command in compile_commands.chain(self.run_command.stages()) {
+            let mut command = command.replace(
+                "{config_dir}",
+                &sh::quote(self.config_dir.as_os_str()).to_string_lossy(),
+            );
+            if let Some(runtime) = &self.runtime {
+                command = command.replace("{runtime}", runtime);
+            }
+            if let Some(program) = command_program(&command) {
+                if !program.contains('{') && !programs.contains(&program) {
+                    programs.push(program);
+                }
+            }
+        }
+        programs.extend(self.sandbox.map(|sandbox| sandbox.program().to_string()));
+        programs
+    }
+
+    /// The `capture_to` file of each compile step, if it has one.
+    fn capture_paths(&self, source: &Path, output: &Path) -> Result<Vec<Option<PathBuf>>> {
+        self.compile_steps()
+            .iter()
+            .map(|step| step.capture_path(source, output))
+            .collect()
+    }
+
+    /// The compile steps with their placeholders expanded. Each step is a group of
+    /// commands to run concurrently; plain entries are groups of one.
+    pub fn format_compile_commands(
+        &self,
+        source: &Path,
+        output: &Path,
+    ) -> Result<Vec<Vec<String>>> {
+        self.compile_steps()
+            .iter()
+            .map(|step| {
+                step.commands()
+                    .iter()
+                    .map(|cmd| format_command(cmd, source, output, Some(self)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Whether the file name of `source` passes the `match_glob` patterns.
+    fn claims(&self, source: &Path) -> bool {
+        let name = source.file_name().unwrap_or_default().to_string_lossy();
+        let matches = |pattern: &str| Pattern::new(pattern).is_ok_and(|p| p.matches(&name));
+        let mut included = None;
+        for pattern in &self.match_glob {
+            match pattern.strip_prefix('!') {
+                Some(excluded) if matches(excluded) => return false,
+                Some(_) => {}
+                None => included = Some(included.unwrap_or(false) || matches(pattern)),
+            }
+        }
+        included.unwrap_or(true)
+    }
+
+    /// The `run_args` followed by the arguments given on the command line.
+    fn program_args(&self, args: &[String]) -> Vec<String> {
+        self.run_args.iter().chain(args).cloned().collect()
+    }
+
+    /// The run command with its placeholders expanded. Without a shell, this is the
+    /// equivalent shell command.
+    pub fn format_run_command(
+        &self,
+        source: &Path,
+        output: &Path,
+        args: &[String],
+    ) -> Result<String> {
+        let output = self.run_target(source, output);
+        if !self.shell.unwrap_or(true) {
+            return Ok(join_argv(&self.format_run_argv(source, output, args)?));
+        }
+        let args = self.program_args(args);
+        let mut format_args = command_args(source, output, Some(self), true);
+        format_args.insert("args_unquoted".to_string(), args.join(" "));
+        format_args.insert("args".to_string(), join_argv(&args));
+        let stages = self.run_command.stages();
+        let append_args = !args.is_empty()
+            && !stages
+                .iter()
+                .any(|stage| stage.contains("{args}") || stage.contains("{args_unquoted}"));
+        let stages = stages
+            .iter()
+            .enumerate()
+            .map(|(index, stage)| {
+                if append_args && index + 1 == stages.len() {
+                    format_template(&format!("{} {{args}}", stage), &format_args)
+                } else {
+                    format_template(stage, &format_args)
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if stages.is_empty() {
+            bail!("Empty run command");
+        }
+        Ok(stages.join(" | "))
+    }
+
+    /// A shell command that runs the program on `input` the way a test case does, for
+    /// reproducing a failure by hand. A pipeline is grouped so that the input reaches
+    /// its first stage. `None` for a directory artifact on stdin, which `cat` can't
+    /// reproduce.
+    pub fn format_repro_command(
+        &self,
+        source: &Path,
+        output: &Path,
+        input: &Path,
+    ) -> Result<Option<String>> {
+        let mut command = self.format_run_command(source, output, &[])?;
+        if self.run_command.stages().len() > 1 {
+            command = format!("( {} )", command);
+        }
+        let quote = |path: &Path| sh::quote(path.as_os_str()).to_string_lossy().into_owned();
+        Ok(match self.artifact_stdin {
+            None => Some(format!("{} < {}", command, quote(input))),
+            Some(_) if output.is_dir() => None,
+            Some(ArtifactStdin::Replace) => Some(format!("{} < {}", command, quote(output))),
+            Some(ArtifactStdin::Prepend) => Some(format!(
+                "cat {} {} | {}",
+                quote(output),
+                quote(input),
+                command
+            )),
+        })
+    }
+
+    /// Split the run command into words and then expand the placeholders in each, for
+    /// running it without a shell. A placeholder never changes how the command is split.
+    fn format_run_argv(
+        &self,
+        source: &Path,
+        output: &Path,
+        args: &[String],
+    ) -> Result<Vec<String>> {
+        let output = self.run_target(source, output);
+        let [command] = self.run_command.stages() else {
+            bail!("A run_command pipeline needs a shell; it cannot be used with shell = false");
+        };
+        let args = self.program_args(args);
+        let mut format_args = command_args(source, output, Some(self), false);
+        format_args.insert("args".to_string(), args.join(" "));
+        let words = words::split_words(command)?;
+        let mut argv = Vec::new();
+        // A word that is just `{args}` becomes one argument per value.
+        for word in &words {
+            if word == "{args}" {
+                argv.extend(args.iter().cloned());
+            } else {
+                argv.push(format_template(word, &format_args)?);
+            }
+        }
+        if !words.iter().any(|word| word.contains("{args}")) {
+            argv.extend(args);
+        }
+        Ok(argv)
+    }
+
+    /// A standalone shell script running the expanded compile and run commands, for
+    /// reproducing a build without `cfb`. It also creates a directory output, strips
+    /// and feeds the artifact to stdin as configured; `use_tempdir` builds, whose
+    /// directory only exists while cfb builds, are refused.
+    pub fn shell_script(&self, source: &Path, output: &Path, args: &[String]) -> Result<String> {
+        let shell = if self.login_shell {
+            "/bin/sh -l"
+        } else {
+            "/bin/sh"
+        };
+        let groups = self.format_compile_commands(source, output)?;
+        if self.use_tempdir.unwrap_or(false) && !groups.is_empty() {
+            bail!("Builds with use_tempdir cannot be exported as a script");
+        }
+        let quote = |path: &Path| sh::quote(path.as_os_str()).to_string_lossy().into_owned();
+        let mut script = format!("#!{}\nset -e\n", shell);
+        if let Some(parent) = output
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty() && !self.interpreted())
+        {
+            script.push_str(&format!("mkdir -p {}\n", quote(parent)));
+        }
+        let output_is_dir = self.output_is_dir.unwrap_or(false);
+        if output_is_dir && !groups.is_empty() {
+            script.push_str(&format!("mkdir -p {}\n", quote(output)));
+        }
+        let captures = self.capture_paths(source, output)?;
+        for (group, capture) in groups.iter().zip(captures) {
+            if let [command] = &group[..] {
+                script.push_str(command);
+                if let Some(capture) = capture {
+                    script.push_str(&format!(
+                        " > {}",
+                        sh::quote(capture.as_os_str()).to_string_lossy()
+                    ));
+                }
+                script.push('\n');
+                continue;
+            }
+            for (i, command) in group.iter().enumerate() {
+                script.push_str(&format!("( {} ) &\npid{}=$!\n", command, i));
+            }
+            for i in 0..group.len() {
+                script.push_str(&format!("wait $pid{}\n", i));
+            }
+        }
+        // Like `strip_artifact`, which leaves directories and missing tools alone.
+        if self.strip.unwrap_or(false) && !groups.is_empty() && !output_is_dir {
+            script.push_str(&format!(
+                "if command -v strip >/dev/null; then strip {}; fi\n",
+                quote(output)
+            ));
+        }
+        let mut command = self.format_run_command(source, output, args)?;
+        if self.run_command.stages().len() > 1 {
+            command = format!("( {} )", command);
+        }
+        match self.artifact_stdin {
+            None => script.push_str(&command),
+            Some(_) if output_is_dir => {
+                bail!("A directory artifact on stdin cannot be exported as a script")
+            }
+            Some(ArtifactStdin::Replace) => {
+                script.push_str(&format!("{} < {}", command, quote(output)))
+            }
+            Some(ArtifactStdin::Prepend) => {
+                script.push_str(&format!("cat {} - | {}", quote(output), command))
+            }
+        }
+        script.push('\n');
+        Ok(script)
+    }
+
+    pub fn build(&self, source: &Path, output: &Path, options: &BuildOptions) -> Result<()> {
+        if self.interpreted() {
+            return Ok(());
+        }
+        check_output_path(source, output)?;
+        let groups = self.format_compile_commands(source, output)?;
+        let captures = self.capture_paths(source, output)?;
+        let mut hashed = groups.concat().join("\n");
+        for capture in captures.iter().flatten() {
+            hashed.push_str(&format!("\n> {}", capture.to_string_lossy()));
+        }
+        let commands_hash = hash::sha256_hex(hashed);
+        let freshness = Freshness::check(source, output, &commands_hash, options);
+        if !groups.is_empty() && EXPLAIN.load(Ordering::Relaxed) {
+            freshness.explain();
+        }
+        if freshness.stale_reason().is_none() {
+            let age = freshness
+                .output_modified
+                .and_then(|modified| modified.elapsed().ok());
+            match age {
+                Some(age) => eprintln!(
+                    "   {} {}",
+                    "skip".yellow().bold(),
+                    format!("(built {} ago)", format_age(age)).bright_black()
+                ),
+                None => eprintln!("   {}", "skip".yellow().bold()),
+            }
+            return Ok(());
+        }
+        if let Some(parent) = output.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // With `use_tempdir`, the commands build `{output}` in the temporary directory
+        // instead; the hash above still covers the commands for the real output.
+        let build_dir = match self.use_tempdir.unwrap_or(false) && !groups.is_empty() {
+            true => Some(BuildDir::create()?),
+            false => None,
+        };
+        let (built, groups, captures) = match &build_dir {
+            Some(dir) => {
+                let built = dir
+                    .0
+                    .join(output.file_name().context("Invalid output path")?);
+                let groups = self.format_compile_commands(source, &built)?;
+                let captures = self.capture_paths(source, &built)?;
+                (built, groups, captures)
+            }
+            None => (output.to_path_buf(), groups, captures),
+        };
+        if self.output_is_dir.unwrap_or(false) {
+            fs::create_dir_all(&built)?;
+        }
+        let BuildOptions {
+            no_skip, retries, ..
+        } = *options;
+        let options = RunOptions {
+            fail_on_stderr: self.fail_on_stderr.unwrap_or(false),
+            login_shell: self.login_shell,
+            quiet: options.quiet,
+            ..RunOptions::default()
+        };
+        let compile_options = RunOptions {
+            current_dir: build_dir.as_ref().map(|dir| dir.0.clone()),
+            ..options.clone()
+        };
+        let compile = |no_skip: bool| -> Result<()> {
+            let options = &compile_options;
+            let steps = self.compile_steps().iter().zip(&groups).zip(&captures);
+            for ((step, group), capture) in steps {
+                if !no_skip && step.is_fresh(source, &built)? {
+                    eprintln!(
+                        "   {} {}",
+                        "skip step".yellow().bold(),
+                        group.join(" & ").bright_black()
+                    );
+                    continue;
+                }
+                if let [command] = &group[..] {
+                    let result = run_command(command, Option::<File>::None, options)?;
+                    if let Some(capture) = capture {
+                        fs::write(capture, result.stdout).with_context(|| {
+                            format!("Failed to write {}", capture.to_string_lossy())
+                        })?;
+                    }
+                    continue;
+                }
+                thread::scope(|scope| {
+                    let handles: Vec<_> = group
+                        .iter()
+                        .map(|command| {
+                            scope.spawn(|| run_command(command, Option::<File>::None, options))
+                        })
+                        .collect();
+                    // Wait for the whole group before reporting the first failure.
+                    let results: Vec<_> = handles
+                        .into_iter()
+                        .map(|handle| handle.join().expect("compile command panicked"))
+                        .collect();
+                    results.into_iter().try_for_each(|result| result.map(drop))
+                })?;
+            }
+            Ok(())
+        };
+        let attempts = if groups.is_empty() { 1 } else { retries + 1 };
+        for attempt in 1..=attempts {
+            if attempts > 1 {
+                eprintln!(
+                    "   {} {}",
+                    "attempt".yellow().bold(),
+                    format!("{}/{}", attempt, attempts).bright_black()
+                );
+            }
+            // A retry re-runs the whole sequence, including the steps that succeeded.
+            match compile(no_skip || attempt > 1) {
+                Err(error) if attempt < attempts => {
+                    report::warn(&format!("Compilation failed, retrying: {}", error))
+                }
+                result => {
+                    result?;
+                    break;
+                }
+            }
+        }
+        if !groups.is_empty() {
+            if self.verify_output.unwrap_or(true) {
+                verify_output(&built)?;
+            }
+            if build_dir.is_some() && built.exists() {
+                copy_artifact(&built, output)?;
+            }
+            if self.verify_output_newer.unwrap_or(false) {
+                verify_output_newer(source, output)?;
+            }
+            if self.strip.unwrap_or(false) {
+                strip_artifact(output, &options)?;
+            }
+            fs::write(commands_hash_path(output), commands_hash)?;
+        }
+        Ok(())
+    }
+
+    pub fn run(
+        &self,
+        source: &Path,
+        output: &Path,
+        stdin: Option<impl io::Read + Send>,
+        options: &RunOptions,
+    ) -> Result<RunOutput> {
+        let options = RunOptions {
+            login_shell: self.login_shell,
+            exit_codes: self.exit_codes.clone(),
+            ..options.clone()
+        };
+        let stdin: Option<Box<dyn io::Read + Send + '_>> = match self.artifact_stdin {
+            None => stdin.map(|stdin| Box::new(stdin) as Box<_>),
+            Some(mode) => {
+                let artifact = io::Cursor::new(read_artifact(output).with_context(|| {
+                    format!("Failed to read artifact {}", output.to_string_lossy())
+                })?);
+                match (mode, stdin) {
+                    (ArtifactStdin::Prepend, Some(stdin)) => Some(Box::new(artifact.chain(stdin))),
+                    _ => Some(Box::new(artifact)),
+                }
+            }
+        };
+        let (process, display) = self.run_process(source, output, &options.args)?;
+        let start = Instant::now();
+        let output = run_process(process, &display, stdin, &options)?;
+        Ok(RunOutput {
+            output,
+            elapsed: start.elapsed(),
+        })
+    }
+
+    /// The process of the run command, with the command to display for it.
+    fn run_process(
+        &self,
+        source: &Path,
+        output: &Path,
+        args: &[String],
+    ) -> Result<(Command, String)> {
+        if !self.shell.unwrap_or(true) {
+            let mut argv = self.format_run_argv(source, output, args)?;
+            if argv.is_empty() {
+                bail!("Empty run command");
+            }
+            // Without a shell, the sandbox runs the words of the command directly.
+            if let Some(sandbox) = self.sandbox {
+                argv.splice(0..0, sandbox.prefix()?.into_iter().map(String::from));
+            }
+            let mut process = Command::new(&argv[0]);
+            process.args(&argv[1..]);
+            return Ok((process, join_argv(&argv)));
+        }
+        let mut command = self.format_run_command(source, output, args)?;
+        if let Some(sandbox) = self.sandbox {
+            command = sandbox.wrap(&command, self.login_shell)?;
+        }
+        Ok((shell_command(&command, self.login_shell), command))
+    }
+}
+
+pub trait CodeRunner {
+    fn build(&self, source: &Path, output: &Path, options: &BuildOptions) -> Result<()>;
+    fn run(
+        &self,
+        source: &Path,
+        output: &Path,
+        stdin: Option<impl io::Read + Send>,
+        options: &RunOptions,
+    ) -> Result<RunOutput>;
+    /// Run an already built artifact without checking whether it needs a rebuild.
+    fn exec(
+        &self,
+        source: &Path,
+        output: &Path,
+        stdin: Option<impl io::Read + Send>,
+        options: &RunOptions,
+    ) -> Result<RunOutput>;
+    fn matches(&self, source: &Path) -> bool;
+}
+
+/// Announce a task, such as `build` or `test`, and what it works on.
+pub fn task_banner(verb: &str, subject: &str) {
+    eprintln!(
+        "{} {} {}",
+        sym::TASK.bright_white().bold(),
+        verb.bright_green().bold(),
+        subject.bright_blue().bold(),
+    );
+}
+
+impl CodeRunner for Config {
+    fn build(&self, source: &Path, output: &Path, options: &BuildOptions) -> Result<()> {
+        let lang_config = self.lang_config(source)?;
+        if !lang_config.interpreted() {
+            task_banner("build", &source.to_string_lossy());
+        }
+        lang_config.build(source, output, &self.quiet_build_options(options))?;
+        Ok(())
+    }
+    fn run(
+        &self,
+        source: &Path,
+        output: &Path,
+        stdin: Option<impl io::Read + Send>,
+        options: &RunOptions,
+    ) -> Result<RunOutput> {
+        let options = &self.quiet_run_options(options);
+        let lang_config = self.lang_config(source)?;
+        if !lang_config.interpreted() {
+            task_banner("build", &source.to_string_lossy());
+        }
+        lang_config.build(source, output, &options.build)?;
+        task_banner("run", &source.to_string_lossy());
+        lang_config.run(source, output, stdin, options)
+    }
+
+    fn exec(
+        &self,
+        source: &Path,
+        output: &Path,
+        stdin: Option<impl io::Read + Send>,
+        options: &RunOptions,
+    ) -> Result<RunOutput> {
+        let lang_config = self.lang_config(source)?;
+        if !lang_config.compile_steps().is_empty() && !output.exists() {
+            bail!(
+                "No build artifact for {} at {}",
+                source.to_string_lossy(),
+                output.to_string_lossy()
+            );
+        }
+        lang_config.run(source, output, stdin, &self.quiet_run_options(options))
+    }
+
+    fn matches(&self, source: &Path) -> bool {
+        self.language(source).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebang_interpreter_is_the_program_name() {
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/python3\nprint()").as_deref(),
+            Some("python3")
+        );
+        assert_eq!(
+            shebang_interpreter("#! /bin/sh -e\n").as_deref(),
+            Some("sh")
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env python3").as_deref(),
+            Some("python3")
+        );
+        assert_eq!(
+            shebang_interpreter("#!/usr/bin/env -S node --flag").as_deref(),
+            Some("node")
+        );
+    }
+
+    #[test]
+    fn shebang_interpreter_needs_a_shebang_on_the_first_line() {
+        assert_eq!(shebang_interpreter("print()\n#!/usr/bin/python3"), None);
+        assert_eq!(shebang_interpreter("#!\n"), None);
+        assert_eq!(shebang_interpreter("#!/usr/bin/env\n"), None);
+        assert_eq!(shebang_interpreter(""), None);
+    }
+
+    #[test]
+    fn command_program_skips_assignments_and_builtins() {
+        assert_eq!(command_program("g++ -O2 {source}").as_deref(), Some("g++"));
+        assert_eq!(
+            command_program("CC=gcc LANG=C make").as_deref(),
+            Some("make")
+        );
+        assert_eq!(
+            command_program("exec python3 {source}").as_deref(),
+            Some("python3")
+        );
+        assert_eq!(command_program("'my tool' -x").as_deref(), Some("my tool"));
+        assert_eq!(command_program("=x cc").as_deref(), Some("=x"));
+        assert_eq!(command_program("cd build && make"), None);
+        assert_eq!(command_program("ulimit -s unlimited; {output}"), None);
+        assert_eq!(command_program("'unterminated"), None);
+    }
+
+    fn raw_langs(toml: &str) -> toml::value::Table {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn resolve_lang_inherits_and_overrides_keys() {
+        let langs = raw_langs(
+            r#"
+            [_base]
+            run_command = "{output}"
+            compile_commands = ["cc {source} -o {output}"]
+
+            [c]
+            base = "_base"
+            compile_commands = ["cc -O2 {source} -o {output}"]
+
+            [cpp]
+            base = "c"
+            fail_on_stderr = true
+            "#,
+        );
+        let cpp = resolve_lang("cpp", &langs, &mut Vec::new()).unwrap();
+        assert_eq!(cpp["run_command"].as_str(), Some("{output}"));
+        assert_eq!(
+            cpp["compile_commands"][0].as_str(),
+            Some("cc -O2 {source} -o {output}")
+        );
+        assert_eq!(cpp["fail_on_stderr"].as_bool(), Some(true));
+        assert!(!cpp.contains_key("base"));
+    }
+
+    #[test]
+    fn resolve_lang_rejects_cyclic_bases() {
+        let langs = raw_langs(
+            r#"
+            [a]
+            base = "b"
+            [b]
+            base = "c"
+            [c]
+            base = "a"
+            [d]
+            base = "d"
+            "#,
+        );
+        let error = resolve_lang("a", &langs, &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cyclic base in language configs: a -> b -> c -> a"
+        );
+        assert!(resolve_lang("d", &langs, &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn resolve_lang_reports_missing_and_invalid_bases() {
+        let langs = raw_langs("[a]\nbase = \"missing\"\n[b]\nbase = 1\n");
+        let error = resolve_lang("a", &langs, &mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "No language config named missing");
+        assert!(resolve_lang("b", &langs, &mut Vec::new()).is_err());
+    }
 }
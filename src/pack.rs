@@ -0,0 +1,122 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use flate2::{write::GzEncoder, Compression};
+use zip::{write::FileOptions, ZipWriter};
+
+use crate::{
+    cli::PackFormat,
+    judge,
+    make::{sym, task_banner},
+};
+
+/// The files to archive, as (path on disk, name in the archive) pairs: the source,
+/// its test case files and, if given, the built artifact under its `cfb-out` path.
+/// Interpreted languages produce no artifact, so a missing one is left out.
+fn pack_entries(source: &Path, artifact: Option<&Path>) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let file_name = |path: &Path| -> Result<PathBuf> {
+        Ok(PathBuf::from(
+            path.file_name().context("Invalid file name")?,
+        ))
+    };
+    let mut entries = vec![(source.to_path_buf(), file_name(source)?)];
+    for case in judge::discover_cases(source)? {
+        for path in [Some(case.input), case.expected].into_iter().flatten() {
+            let name = file_name(&path)?;
+            entries.push((path, name));
+        }
+    }
+    if let Some(artifact) = artifact.filter(|artifact| artifact.exists()) {
+        entries.push((artifact.to_path_buf(), artifact.to_path_buf()));
+    }
+    Ok(entries)
+}
+
+fn write_tar_gz(archive: &Path, entries: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let encoder = GzEncoder::new(File::create(archive)?, Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (path, name) in entries {
+        if path.is_dir() {
+            builder.append_dir_all(name, path)?;
+        } else {
+            builder.append_path_with_name(path, name)?;
+        }
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Replace directory entries by the files below them, for formats without
+/// directory support in the writer.
+fn expand_dirs(entries: &[(PathBuf, PathBuf)]) -> Result<Vec<(PathBuf, PathBuf)>> {
+    let mut files = Vec::new();
+    for (path, name) in entries {
+        if path.is_dir() {
+            let children = fs::read_dir(path)?
+                .map(|entry| {
+                    let entry = entry?;
+                    Ok((entry.path(), name.join(entry.file_name())))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            files.extend(expand_dirs(&children)?);
+        } else {
+            files.push((path.clone(), name.clone()));
+        }
+    }
+    Ok(files)
+}
+
+fn write_zip(archive: &Path, entries: &[(PathBuf, PathBuf)]) -> Result<()> {
+    let mut writer = ZipWriter::new(File::create(archive)?);
+    for (path, name) in &expand_dirs(entries)? {
+        let mut options = FileOptions::default();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            options = options.unix_permissions(path.metadata()?.permissions().mode());
+        }
+        writer.start_file(name.to_string_lossy(), options)?;
+        io::copy(&mut File::open(path)?, &mut writer)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+/// Bundle a source with its test cases (and optionally its built artifact) into an
+/// archive in `out_dir`, returning the archive's path.
+pub fn pack(
+    source: &Path,
+    artifact: Option<&Path>,
+    format: PackFormat,
+    out_dir: &Path,
+) -> Result<PathBuf> {
+    let stem = source
+        .file_stem()
+        .context("Invalid source file name")?
+        .to_string_lossy();
+    let archive = out_dir.join(match format {
+        PackFormat::Zip => format!("{}.zip", stem),
+        PackFormat::TarGz => format!("{}.tar.gz", stem),
+    });
+    task_banner("pack", &archive.to_string_lossy());
+    let entries = pack_entries(source, artifact)?;
+    for (_, name) in &entries {
+        eprintln!(
+            "{} {}",
+            sym::FILE.bright_white().bold(),
+            name.to_string_lossy()
+        );
+    }
+    match format {
+        PackFormat::Zip => write_zip(&archive, &entries),
+        PackFormat::TarGz => write_tar_gz(&archive, &entries),
+    }
+    .with_context(|| format!("Failed to write {}", archive.to_string_lossy()))?;
+    eprintln!("   {} files packed", entries.len());
+    Ok(archive)
+}
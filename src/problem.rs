@@ -0,0 +1,132 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::judge::TestCase;
+
+const PROBLEM_FILE: &str = ".cfb/problem.toml";
+
+#[derive(Deserialize)]
+struct ProblemTest {
+    input: PathBuf,
+    expected: Option<PathBuf>,
+    max_output_bytes: Option<u64>,
+    expected_exit_code: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct ProblemFile {
+    source: Option<PathBuf>,
+    time_limit_ms: Option<u64>,
+    #[serde(default)]
+    tests: Vec<ProblemTest>,
+}
+
+/// Per-problem metadata read from `.cfb/problem.toml`. Paths in the file are
+/// relative to the problem directory (the one containing `.cfb`).
+pub struct Problem {
+    pub source: Option<PathBuf>,
+    pub time_limit: Option<Duration>,
+    pub tests: Vec<TestCase>,
+}
+
+impl ProblemTest {
+    /// The test case, with its paths resolved relative to `dir`.
+    fn into_case(self, dir: &Path) -> TestCase {
+        TestCase {
+            name: self.input.to_string_lossy().into_owned(),
+            input: dir.join(self.input),
+            expected: self.expected.map(|expected| dir.join(expected)),
+            max_output_bytes: self.max_output_bytes,
+            expected_exit_code: self.expected_exit_code,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct TestsFile {
+    #[serde(default)]
+    tests: Vec<ProblemTest>,
+}
+
+/// The cases listed in `<stem>.tests.toml` next to a source, for inputs that do not
+/// follow the `<stem>.in` naming. Paths are relative to the source's directory.
+pub fn load_listed_cases(source: &Path) -> Result<Vec<TestCase>> {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let path = source.with_file_name(format!("{}.tests.toml", stem));
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = toml::from_str::<TestsFile>(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse tests file: {}", path.to_string_lossy()))?;
+    let dir = source.parent().unwrap_or_else(|| Path::new(""));
+    Ok(file
+        .tests
+        .into_iter()
+        .map(|test| test.into_case(dir))
+        .collect())
+}
+
+#[derive(Deserialize)]
+struct LimitsFile {
+    time_limit_ms: Option<u64>,
+    memory_mb: Option<u64>,
+}
+
+/// The time and memory limits of a single source, from `<stem>.limits.toml` next to it.
+#[derive(Default)]
+pub struct Limits {
+    pub time_limit: Option<Duration>,
+    /// In bytes.
+    pub memory_limit: Option<u64>,
+}
+
+/// Load the limits sidecar of `source`; without one, there are no limits.
+pub fn load_limits(source: &Path) -> Result<Limits> {
+    let stem = source.file_stem().unwrap_or_default().to_string_lossy();
+    let path = source.with_file_name(format!("{}.limits.toml", stem));
+    if !path.exists() {
+        return Ok(Limits::default());
+    }
+    let file = toml::from_str::<LimitsFile>(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse limits file: {}", path.to_string_lossy()))?;
+    Ok(Limits {
+        time_limit: file.time_limit_ms.map(Duration::from_millis),
+        memory_limit: file
+            .memory_mb
+            .map(megabytes)
+            .transpose()
+            .with_context(|| format!("Invalid limits file: {}", path.to_string_lossy()))?,
+    })
+}
+
+/// A memory limit in megabytes as bytes, failing if that does not fit.
+pub fn megabytes(mb: u64) -> Result<u64> {
+    mb.checked_mul(1024 * 1024)
+        .with_context(|| format!("The memory limit of {} MB is too large", mb))
+}
+
+/// Load the problem metadata of `dir`, if it has any.
+pub fn load_problem(dir: &Path) -> Result<Option<Problem>> {
+    let path = dir.join(PROBLEM_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let file = toml::from_str::<ProblemFile>(&fs::read_to_string(&path)?)
+        .with_context(|| format!("Failed to parse problem file: {}", path.to_string_lossy()))?;
+    let tests = file
+        .tests
+        .into_iter()
+        .map(|test| test.into_case(dir))
+        .collect();
+    Ok(Some(Problem {
+        source: file.source.map(|source| dir.join(source)),
+        time_limit: file.time_limit_ms.map(Duration::from_millis),
+        tests,
+    }))
+}
@@ -0,0 +1,78 @@
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    time::Instant,
+};
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::make::{task_banner, BuildOptions, CodeRunner, CommandTimedOut, Config, RunOptions};
+
+/// A byte count in the largest fitting binary unit, such as `3.2 MiB`.
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Build and run a source once, then report how long each phase took, the peak
+/// memory of the run and its exit status. The program runs with the environment and
+/// limits of `cfb test`.
+pub fn profile(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    stdin: Option<impl Read + Send>,
+    stdin_terminal: bool,
+) -> Result<()> {
+    let build_start = Instant::now();
+    config.build(source, output, &BuildOptions::default())?;
+    let build_time = build_start.elapsed();
+    task_banner("profile", &source.to_string_lossy());
+    let options = RunOptions {
+        allow_failure: true,
+        stdin_terminal,
+        ..config.run_options(source, output)?
+    };
+    let result = match config.exec(source, output, stdin, &options) {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(timed_out) = e.downcast_ref::<CommandTimedOut>() {
+                io::stdout().write_all(&timed_out.output.combined())?;
+            }
+            return Err(e);
+        }
+    };
+    io::stdout().write_all(&result.output.combined())?;
+
+    let exit = match result.output.exit_code {
+        Some(code) => code.to_string(),
+        None => "killed by a signal".to_string(),
+    };
+    let rows = [
+        ("compile", format!("{} ms", build_time.as_millis())),
+        ("run", format!("{} ms", result.elapsed.as_millis())),
+        (
+            "memory",
+            result
+                .output
+                .peak_memory
+                .map_or("n/a".to_string(), format_bytes),
+        ),
+        ("exit", exit),
+    ];
+    for (name, value) in rows {
+        eprintln!("   {} {}", format!("{:<8}", name).yellow().bold(), value);
+    }
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::{
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, RecvTimeoutError},
+    },
+    time::{Duration, Instant},
+};
+
+use colored::Colorize;
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const INTERVAL: Duration = Duration::from_millis(100);
+/// Commands that finish quicker than this never show the indicator.
+const DELAY: Duration = Duration::from_secs(1);
+
+/// Only one indicator is drawn at a time, e.g. when test cases run in parallel.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Draw a spinner with the elapsed time on stderr until `stop` is disconnected,
+/// then erase it. Does nothing when stderr is not a terminal.
+pub fn heartbeat(stop: Receiver<()>) {
+    if !io::stderr().is_terminal() || ACTIVE.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let start = Instant::now();
+    let mut shown = false;
+    for frame in FRAMES.iter().cycle() {
+        if stop.recv_timeout(INTERVAL) != Err(RecvTimeoutError::Timeout) {
+            break;
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= DELAY {
+            let line = format!("{} {:.1}s", frame, elapsed.as_secs_f64());
+            eprint!("\r{}", line.bright_black());
+            let _ = io::stderr().flush();
+            shown = true;
+        }
+    }
+    if shown {
+        eprint!("\r\x1b[2K");
+    }
+    ACTIVE.store(false, Ordering::SeqCst);
+}
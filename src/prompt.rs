@@ -0,0 +1,24 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+
+/// Ask a yes/no question before a destructive operation. With `assume_yes` the
+/// answer is yes; without a terminal on stdin to ask on, as in scripts, it is an
+/// error.
+pub fn confirm(question: &str, assume_yes: bool) -> Result<bool> {
+    if assume_yes {
+        return Ok(true);
+    }
+    if !io::stdin().is_terminal() {
+        bail!(
+            "Cannot ask \"{}\" without a terminal; pass -y to confirm",
+            question
+        );
+    }
+    eprint!("   {} [y/N] ", question.yellow().bold());
+    io::stderr().flush()?;
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
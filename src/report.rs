@@ -0,0 +1,72 @@
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use serde::Serialize;
+
+use crate::make::CommandOutput;
+
+/// Print a warning about something that does not stop the command.
+pub fn warn(message: &str) {
+    eprintln!(
+        "   {} {}",
+        "warning".yellow().bold(),
+        message.bright_black()
+    );
+}
+
+/// One run of a program in a `--report` file. Output streams are decoded lossily.
+#[derive(Serialize)]
+pub struct RunRecord {
+    pub source: String,
+    /// The test case, for runs of `cfb test`.
+    pub case: Option<String>,
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    pub duration_ms: Option<f64>,
+    /// `OK` or `RE` for a plain run, the verdict label for a test case, and `error`
+    /// if the program could not be run to completion.
+    pub verdict: String,
+    pub error: Option<String>,
+}
+
+impl RunRecord {
+    pub fn new(
+        source: &Path,
+        case: Option<&str>,
+        output: Option<&CommandOutput>,
+        elapsed: Option<Duration>,
+        verdict: &str,
+    ) -> Self {
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        RunRecord {
+            source: source.to_string_lossy().into_owned(),
+            case: case.map(str::to_string),
+            exit_code: output.and_then(|output| output.exit_code),
+            stdout: output
+                .map(|output| text(&output.stdout))
+                .unwrap_or_default(),
+            stderr: output
+                .map(|output| text(&output.stderr))
+                .unwrap_or_default(),
+            duration_ms: elapsed.map(|elapsed| elapsed.as_secs_f64() * 1000.0),
+            verdict: verdict.to_string(),
+            error: None,
+        }
+    }
+
+    /// The record of a run that failed with `error`.
+    pub fn failed(source: &Path, case: Option<&str>, error: &anyhow::Error) -> Self {
+        RunRecord {
+            error: Some(format!("{:#}", error)),
+            ..RunRecord::new(source, case, None, None, "error")
+        }
+    }
+}
+
+/// Write the records of an invocation to `path` as a JSON array, replacing the file.
+pub fn write(path: &Path, records: &[RunRecord]) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(records)?)
+        .with_context(|| format!("Failed to write report {}", path.to_string_lossy()))
+}
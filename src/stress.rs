@@ -0,0 +1,87 @@
+use std::{fs, io::Cursor, path::Path};
+
+use anyhow::{bail, Context, Result};
+use colored::Colorize;
+
+use crate::make::{self, task_banner, BuildOptions, CodeRunner, Config, RunOptions};
+
+pub struct StressOptions {
+    /// Prints a random input; `{seed}` is replaced by the seed of the iteration.
+    pub generator: String,
+    /// Prints the expected output for the input on its stdin.
+    pub expected_command: String,
+    pub iterations: u64,
+    /// The seed of the first iteration; each following one uses the next number.
+    pub seed: u64,
+}
+
+/// Where the input of a failed stress iteration is saved.
+fn failing_input_path(output: &Path) -> std::path::PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".stress.in");
+    path.into()
+}
+
+/// Build a source, then run it on generated inputs until its output differs from
+/// that of the reference command. The seed of a failing input is reported, so that
+/// `--seed <seed> --iterations 1` reproduces it.
+pub fn stress(
+    config: &Config,
+    source: &Path,
+    output: &Path,
+    options: &StressOptions,
+) -> Result<()> {
+    let checker = config.checker_for(source)?;
+    config.build(source, output, &BuildOptions::default())?;
+    task_banner("stress", &source.to_string_lossy());
+    let quiet = RunOptions {
+        login_shell: config.login_shell(),
+        quiet: true,
+        ..RunOptions::default()
+    };
+    let run_options = RunOptions {
+        allow_failure: true,
+        quiet: true,
+        ..config.run_options(source, output)?
+    };
+    for seed in (0..options.iterations).map(|i| options.seed.wrapping_add(i)) {
+        let generator = options.generator.replace("{seed}", &seed.to_string());
+        let generator = config.format_command(&generator, source, output)?;
+        let input = make::run_command(&generator, Option::<&[u8]>::None, &quiet)
+            .with_context(|| format!("Generator failed with seed {}", seed))?
+            .stdout;
+        let expected = make::run_command(&options.expected_command, Some(&input[..]), &quiet)
+            .with_context(|| format!("Reference command failed with seed {}", seed))?;
+        let result = config.exec(source, output, Some(Cursor::new(&input)), &run_options)?;
+        let failure = match result.output.exit_code {
+            Some(0) => checker.compare(&result.output.stdout, &expected.stdout),
+            Some(code) => Some(format!("exit code {}", code)),
+            None => Some("killed by a signal".to_string()),
+        };
+        let Some(failure) = failure else {
+            continue;
+        };
+        let saved = failing_input_path(output);
+        fs::write(&saved, &input)
+            .with_context(|| format!("Failed to write {}", saved.to_string_lossy()))?;
+        eprintln!(
+            "   {} {} {}",
+            "failed".bright_red().bold(),
+            format!("seed {}", seed).bright_blue(),
+            failure.bright_black()
+        );
+        eprintln!(
+            "   {} {}",
+            "input".yellow().bold(),
+            saved.to_string_lossy().bright_black()
+        );
+        bail!("Stress test failed with seed {}", seed);
+    }
+    eprintln!(
+        "   {}",
+        format!("{} iterations passed", options.iterations)
+            .green()
+            .bold()
+    );
+    Ok(())
+}
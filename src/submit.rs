@@ -0,0 +1,62 @@
+use std::{io::Write, path::Path};
+
+use anyhow::{bail, Result};
+use colored::Colorize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use shell_quote::sh;
+
+use crate::make::{self, task_banner, Config, RunOptions};
+
+/// The `[submit]` config section. One of `url` and `command` has to be set.
+#[derive(Deserialize, Serialize, JsonSchema, Clone)]
+pub struct SubmitConfig {
+    /// Upload the source with curl, as the `source` field of a multipart POST to this URL.
+    url: Option<String>,
+    /// A command that submits `{source}`, used instead of the `url` upload.
+    command: Option<String>,
+    /// Run the local tests first and only submit if they all pass.
+    test_first: Option<bool>,
+}
+
+impl SubmitConfig {
+    pub fn test_first(&self) -> bool {
+        self.test_first.unwrap_or(false)
+    }
+
+    /// The submission command for a source, with its placeholders expanded.
+    fn command(&self, config: &Config, source: &Path, output: &Path) -> Result<String> {
+        match (&self.command, &self.url) {
+            (Some(command), _) => config.format_command(command, source, output),
+            (None, Some(url)) => Ok(format!(
+                "{} {}",
+                config.format_command(
+                    "curl -sS --fail-with-body -F source=@{source}",
+                    source,
+                    output
+                )?,
+                sh::quote(url.as_str()).to_string_lossy()
+            )),
+            (None, None) => bail!("The [submit] section needs a url or a command"),
+        }
+    }
+}
+
+/// Submit a source and print the response of the server.
+pub fn submit(config: &Config, submit: &SubmitConfig, source: &Path, output: &Path) -> Result<()> {
+    let command = submit.command(config, source, output)?;
+    task_banner("submit", &source.to_string_lossy());
+    let options = RunOptions {
+        login_shell: config.login_shell(),
+        allow_failure: true,
+        ..RunOptions::default()
+    };
+    let response = make::run_command(&command, Option::<&[u8]>::None, &options)?;
+    std::io::stdout().write_all(&response.combined())?;
+    match response.exit_code {
+        Some(0) => eprintln!("   {}", "submitted".green().bold()),
+        Some(code) => bail!("Submission failed (exit code {})", code),
+        None => bail!("Submission command was killed by a signal"),
+    }
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::{
+    cmp::Ordering,
+    fs,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    str::Chars,
+};
+
+use anyhow::Result;
+
+use crate::make::{CodeRunner, Config};
+
+/// Directory names that are never searched for sources.
+const IGNORED_DIRS: &[&str] = &["cfb-out", "target"];
+
+fn visit(dir: &Path, config: &Config, sources: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            if !IGNORED_DIRS.contains(&name.as_ref()) {
+                visit(&path, config, sources)?;
+            }
+        } else if path.is_file() && config.matches(&path) {
+            sources.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Every file below `root` that some language config can build, in sorted order.
+/// Hidden entries, artifact directories and special files such as FIFOs are skipped.
+pub fn find_sources(root: &Path, config: &Config) -> Result<Vec<PathBuf>> {
+    let mut sources = Vec::new();
+    visit(root, config, &mut sources)?;
+    sources.sort();
+    Ok(sources)
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+        number.push(c);
+    }
+    number
+}
+
+/// Compare names with runs of digits ordered by their numeric value, so that `2`
+/// sorts before `10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a_chars.peek(), b_chars.peek()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a_chars);
+                let y = take_number(&mut b_chars);
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                a_chars.next();
+                b_chars.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// The non-hidden files directly in `dir`, in natural order of their names.
+pub fn list_inputs(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with('.') && entry.file_type()?.is_file() {
+            inputs.push(entry.path());
+        }
+    }
+    inputs.sort_by(|a, b| {
+        natural_cmp(
+            &a.file_name().unwrap_or_default().to_string_lossy(),
+            &b.file_name().unwrap_or_default().to_string_lossy(),
+        )
+    });
+    Ok(inputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_sort_by_value() {
+        let mut names = ["10.in", "2.in", "1.in", "b.in", "a10.in", "a9.in"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["1.in", "2.in", "10.in", "a9.in", "a10.in", "b.in"]);
+    }
+
+    #[test]
+    fn leading_zeros_do_not_change_the_value() {
+        assert_eq!(natural_cmp("test007", "test7x"), Ordering::Less);
+        assert_eq!(natural_cmp("test010", "test9"), Ordering::Greater);
+        // Names of equal value fall back to plain comparison, keeping the order total.
+        assert_eq!(natural_cmp("07", "7"), Ordering::Less);
+        assert_eq!(natural_cmp("7", "7"), Ordering::Equal);
+    }
+
+    #[test]
+    fn prefixes_sort_first() {
+        assert_eq!(natural_cmp("case", "case1"), Ordering::Less);
+        assert_eq!(natural_cmp("case1", "case"), Ordering::Greater);
+        assert_eq!(natural_cmp("", "a"), Ordering::Less);
+    }
+
+    #[test]
+    fn long_numbers_do_not_overflow() {
+        assert_eq!(
+            natural_cmp("99999999999999999999999", "100000000000000000000000"),
+            Ordering::Less
+        );
+    }
+}
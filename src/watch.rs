@@ -0,0 +1,40 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use colored::Colorize;
+
+use crate::make::task_banner;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+const SEPARATOR_WIDTH: usize = 60;
+
+/// Run `action` now and again every time `path` is modified, clearing the screen
+/// before each run, or with `clear` unset, separating the runs with a line instead.
+/// Errors from `action` are reported without stopping the loop.
+pub fn watch(path: &Path, clear: bool, mut action: impl FnMut() -> Result<()>) -> Result<()> {
+    let mut last_modified = None;
+    loop {
+        // Editors that save by replacing the file can make it briefly disappear.
+        let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+        if modified.is_some() && modified != last_modified {
+            if clear {
+                print!("\x1b[2J\x1b[H");
+                io::stdout().flush()?;
+            } else if last_modified.is_some() {
+                eprintln!("{}", "-".repeat(SEPARATOR_WIDTH).bright_black());
+            }
+            last_modified = modified;
+            if let Err(e) = action() {
+                eprintln!("{} {:#}", "error:".bright_red().bold(), e);
+            }
+            task_banner("watch", &path.to_string_lossy());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,109 @@
+use anyhow::{bail, Result};
+
+/// Split a command into words the way a POSIX shell would, honouring single and
+/// double quotes and backslash escapes, but without any expansions.
+pub fn split_words(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated single quote in `{}`", command),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => bail!("Unterminated double quote in `{}`", command),
+                        },
+                        Some(c) => word.push(c),
+                        None => bail!("Unterminated double quote in `{}`", command),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                match chars.next() {
+                    Some(c) => word.push(c),
+                    None => bail!("Trailing backslash in `{}`", command),
+                }
+            }
+            c => {
+                in_word = true;
+                word.push(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(command: &str) -> Vec<String> {
+        split_words(command).unwrap()
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(words("  cc  -O2\tsol.c \n"), ["cc", "-O2", "sol.c"]);
+        assert!(words("   ").is_empty());
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        assert_eq!(words(r#"echo 'a "b" \c'"#), ["echo", r#"a "b" \c"#]);
+    }
+
+    #[test]
+    fn double_quotes_only_escape_special_characters() {
+        assert_eq!(
+            words(r#"echo "a \"b\" \$c \n""#),
+            ["echo", r#"a "b" $c \n"#]
+        );
+    }
+
+    #[test]
+    fn quotes_join_adjacent_parts_and_keep_empty_words() {
+        assert_eq!(words(r#"-o'out put'"s" '' """#), ["-oout puts", "", ""]);
+    }
+
+    #[test]
+    fn backslash_escapes_the_next_character() {
+        assert_eq!(words(r"a\ b \'c"), ["a b", "'c"]);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert!(split_words("echo 'a").is_err());
+        assert!(split_words(r#"echo "a"#).is_err());
+        assert!(split_words(r#"echo "a\"#).is_err());
+        assert!(split_words(r"echo a\").is_err());
+    }
+}